@@ -0,0 +1,23 @@
+; Harness self check, loads the mooneye pass signature into B, C, D, E, H, L
+; and stops on the LD B,B software breakpoint. Only uses instructions the
+; conformance vectors cover, so tests/roms.rs always has a rom to pass.
+;
+; rgbasm -o harness_pass.o harness_pass.s
+; rgblink -o harness_pass.gb harness_pass.o
+; rgbfix -v -t "GBOY HARNESS" harness_pass.gb
+
+SECTION "entry", ROM0[$100]
+    nop
+    jp start
+
+SECTION "main", ROM0[$150]
+start:
+    ld b, 3
+    ld c, 5
+    ld d, 8
+    ld e, 13
+    ld h, 21
+    ld l, 34
+    ld b, b
+.halt:
+    jr .halt
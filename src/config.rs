@@ -0,0 +1,78 @@
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::gboy::screen::{ColorCorrection, DmgPalette};
+
+/// settings read from a json file, command line options take precedence
+///
+/// {"palette": "pocket", "color_correction": "lcd"}
+#[derive(Debug, Default)]
+pub struct Config {
+    pub palette: Option<DmgPalette>,
+    pub color_correction: Option<ColorCorrection>,
+}
+
+/// $XDG_CONFIG_HOME/gboy/config.json, falling back to ~/.config
+pub fn default_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("gboy").join("config.json"))
+}
+
+fn invalid(e: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn field<T: std::str::FromStr<Err = String>>(json: &Value, name: &str) -> Result<Option<T>, io::Error> {
+    match json.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => s.parse().map(Some).map_err(invalid),
+        Some(v) => Err(invalid(format!("{} must be a string, got {}", name, v))),
+    }
+}
+
+pub fn load(path: &Path) -> Result<Config, io::Error> {
+    let json: Value = serde_json::from_str(&fs::read_to_string(path)?).map_err(|e| invalid(e.to_string()))?;
+    Ok(Config {
+        palette: field(&json, "palette")?,
+        color_correction: field(&json, "color_correction")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_json(name: &str, json: &str) -> Result<Config, io::Error> {
+        let path = env::temp_dir().join(format!("gboy-config-{}-{}.json", std::process::id(), name));
+        fs::write(&path, json).unwrap();
+        let config = load(&path);
+        let _ = fs::remove_file(&path);
+        config
+    }
+
+    #[test]
+    fn test_load() {
+        let config = load_json("full", r#"{"palette": "pocket", "color_correction": "lcd"}"#).unwrap();
+        assert_eq!(config.palette, Some(DmgPalette::Pocket));
+        assert_eq!(config.color_correction, Some(ColorCorrection::Lcd));
+
+        // missing and null settings are left to the defaults
+        let config = load_json("partial", r#"{"palette": null}"#).unwrap();
+        assert_eq!(config.palette, None);
+        assert_eq!(config.color_correction, None);
+    }
+
+    #[test]
+    fn test_load_rejects_bad_settings() {
+        assert!(load_json("value", r#"{"palette": "purple"}"#).is_err());
+        assert!(load_json("type", r#"{"color_correction": true}"#).is_err());
+        assert!(load_json("syntax", "{palette: pocket}").is_err());
+        assert!(load(Path::new("/nonexistent/gboy/config.json")).is_err());
+    }
+}
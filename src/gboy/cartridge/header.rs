@@ -0,0 +1,491 @@
+use std::io;
+
+use super::mapper::{self, RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+pub const HEADER_END: usize = 0x150;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CgbFlag {
+    // 0x143 holds the last title character
+    #[default]
+    Dmg,
+    // 0x80, runs on both
+    Compatible,
+    // 0xC0, refuses to run on DMG
+    Only,
+}
+
+/// cartridge header found on 0x100..=0x14F
+#[derive(Debug, Clone, Default)]
+pub struct Header {
+    pub title: String,
+    /// 4 characters on 0x13F..=0x142, only on CGB era carts using an 11 characters title
+    pub manufacturer: Option<String>,
+    pub cgb: CgbFlag,
+    /// 2 ASCII characters on 0x144..=0x145, used when old_licensee is 0x33
+    pub new_licensee: String,
+    pub sgb: bool,
+    pub cartridge_type: u8,
+    /// in bytes, as declared on 0x148
+    pub rom_size: usize,
+    /// in bytes, as declared on 0x149
+    pub ram_size: usize,
+    pub destination: u8,
+    pub old_licensee: u8,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+/// header characters, anything outside printable ASCII shows up as '?'
+fn text(bytes: &[u8]) -> String {
+    bytes.iter()
+        .take_while(|b| **b != 0x0)
+        .map(|b| if *b >= 0x20 && *b < 0x7F { *b as char } else { '?' })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+pub fn parse(rom: &[u8]) -> Result<Header, io::Error> {
+    if rom.len() < HEADER_END {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("rom too short for a cartridge header, {} bytes", rom.len()),
+        ));
+    }
+
+    let cgb = match rom[0x143] {
+        0x80 => CgbFlag::Compatible,
+        0xC0 => CgbFlag::Only,
+        _ => CgbFlag::Dmg,
+    };
+    // CGB carts use 15 characters titles, later ones 11 plus a manufacturer code
+    let manufacturer = &rom[0x13F..0x143];
+    let has_manufacturer = cgb != CgbFlag::Dmg
+        && manufacturer.iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit());
+    let (title, manufacturer) = match cgb {
+        CgbFlag::Dmg => (text(&rom[0x134..0x144]), None),
+        _ if has_manufacturer => (text(&rom[0x134..0x13F]), Some(text(manufacturer))),
+        _ => (text(&rom[0x134..0x143]), None),
+    };
+
+    Ok(Header {
+        title,
+        manufacturer,
+        cgb,
+        new_licensee: text(&rom[0x144..0x146]),
+        // SGB functions are only enabled with the new licensee code in use
+        sgb: rom[0x146] == 0x03 && rom[0x14B] == 0x33,
+        cartridge_type: rom[0x147],
+        rom_size: rom_size(rom[0x148]),
+        ram_size: ram_size(rom[0x149]),
+        destination: rom[0x14A],
+        old_licensee: rom[0x14B],
+        version: rom[0x14C],
+        header_checksum: rom[0x14D],
+        global_checksum: (rom[0x14E] as u16) << 8 | rom[0x14F] as u16,
+    })
+}
+
+/// rom size from header code at 0x148
+fn rom_size(code: u8) -> usize {
+    match code {
+        0x00..=0x08 => (2 * ROM_BANK_SIZE) << code,
+        0x52 => 72 * ROM_BANK_SIZE,
+        0x53 => 80 * ROM_BANK_SIZE,
+        0x54 => 96 * ROM_BANK_SIZE,
+        _ => 0x0,
+    }
+}
+
+/// external ram size from header code at 0x149
+fn ram_size(code: u8) -> usize {
+    match code {
+        0x1 => 0x800,
+        0x2 => RAM_BANK_SIZE,
+        0x3 => 4 * RAM_BANK_SIZE,
+        0x4 => 16 * RAM_BANK_SIZE,
+        0x5 => 8 * RAM_BANK_SIZE,
+        _ => 0x0,
+    }
+}
+
+impl Header {
+    pub fn cartridge_type_name(&self) -> String {
+        match cart_type_name(self.cartridge_type) {
+            Some(name) => name.to_string(),
+            None => format!("unsupported mapper 0x{:02X}", self.cartridge_type),
+        }
+    }
+
+    /// bank controller emulated for this cartridge type, None when unsupported
+    pub fn mapper_name(&self) -> Option<&'static str> {
+        mapper::kind(self.cartridge_type).map(mapper::Kind::name)
+    }
+
+    /// cartridge types with a battery keeping external ram (and clock) alive
+    pub fn has_battery(&self) -> bool {
+        matches!(self.cartridge_type, 0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFC | 0xFE | 0xFF)
+    }
+
+    pub fn destination_name(&self) -> &'static str {
+        match self.destination {
+            0x0 => "Japanese",
+            0x1 => "Non-Japanese",
+            _ => "Not defined",
+        }
+    }
+
+    /// publisher, from the new licensee code when the old one points there
+    pub fn licensee(&self) -> String {
+        let name = if self.old_licensee == 0x33 {
+            new_licensee_name(&self.new_licensee)
+        } else {
+            old_licensee_name(self.old_licensee)
+        };
+        match name {
+            Some(n) => n.to_string(),
+            None if self.old_licensee == 0x33 => format!("Unknown ({})", self.new_licensee),
+            None => format!("Unknown ({:#04x})", self.old_licensee),
+        }
+    }
+}
+
+/// cartridge type names from header code at 0x147
+pub fn cart_type_name(code: u8) -> Option<&'static str> {
+    let name = match code {
+        0x00 => "Rom Only",
+        0x01 => "MBC1",
+        0x02 => "MBC1+RAM",
+        0x03 => "MBC1+RAM+BATTERY",
+        0x05 => "MBC2",
+        0x06 => "MBC2+BATTERY",
+        0x08 => "ROM+RAM",
+        0x09 => "ROM+RAM+BATTERY",
+        0x0B => "MMM01",
+        0x0C => "MMM01+RAM",
+        0x0D => "MMM01+RAM+BATTERY",
+        0x0F => "MBC3+TIMER+BATTERY",
+        0x10 => "MBC3+TIMER+RAM+BATTERY",
+        0x11 => "MBC3",
+        0x12 => "MBC3+RAM",
+        0x13 => "MBC3+RAM+BATTERY",
+        0x19 => "MBC5",
+        0x1A => "MBC5+RAM",
+        0x1B => "MBC5+RAM+BATTERY",
+        0x1C => "MBC5+RUMBLE",
+        0x1D => "MBC5+RUMBLE+RAM",
+        0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+        0x20 => "MBC6",
+        0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+        0xFC => "POCKET CAMERA",
+        0xFD => "BANDAI TAMA5",
+        0xFE => "HuC3",
+        0xFF => "HuC1+RAM+BATTERY",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn new_licensee_name(code: &str) -> Option<&'static str> {
+    let name = match code {
+        "00" => "None",
+        "01" => "Nintendo R&D1",
+        "08" => "Capcom",
+        "13" => "Electronic Arts",
+        "18" => "Hudson Soft",
+        "19" => "B-AI",
+        "20" => "KSS",
+        "22" => "POW",
+        "24" => "PCM Complete",
+        "25" => "San-X",
+        "28" => "Kemco Japan",
+        "29" => "Seta",
+        "30" => "Viacom",
+        "31" => "Nintendo",
+        "32" => "Bandai",
+        "33" => "Ocean/Acclaim",
+        "34" => "Konami",
+        "35" => "Hector",
+        "37" => "Taito",
+        "38" => "Hudson",
+        "39" => "Banpresto",
+        "41" => "Ubisoft",
+        "42" => "Atlus",
+        "44" => "Malibu",
+        "46" => "Angel",
+        "47" => "Bullet-Proof",
+        "49" => "Irem",
+        "50" => "Absolute",
+        "51" => "Acclaim",
+        "52" => "Activision",
+        "53" => "American Sammy",
+        "54" => "Konami",
+        "55" => "Hi Tech Entertainment",
+        "56" => "LJN",
+        "57" => "Matchbox",
+        "58" => "Mattel",
+        "59" => "Milton Bradley",
+        "60" => "Titus",
+        "61" => "Virgin",
+        "64" => "LucasArts",
+        "67" => "Ocean",
+        "69" => "Electronic Arts",
+        "70" => "Infogrames",
+        "71" => "Interplay",
+        "72" => "Broderbund",
+        "73" => "Sculptured",
+        "75" => "SCi",
+        "78" => "THQ",
+        "79" => "Accolade",
+        "80" => "Misawa",
+        "83" => "Lozc",
+        "86" => "Tokuma Shoten Intermedia",
+        "87" => "Tsukuda Original",
+        "91" => "Chunsoft",
+        "92" => "Video System",
+        "93" => "Ocean/Acclaim",
+        "95" => "Varie",
+        "96" => "Yonezawa/S'Pal",
+        "97" => "Kaneko",
+        "99" => "Pack-In-Soft",
+        "9H" => "Bottom Up",
+        "A4" => "Konami (Yu-Gi-Oh!)",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn old_licensee_name(code: u8) -> Option<&'static str> {
+    let name = match code {
+        0x00 => "None",
+        0x01 => "Nintendo",
+        0x08 => "Capcom",
+        0x09 => "Hot-B",
+        0x0A => "Jaleco",
+        0x0B => "Coconuts Japan",
+        0x0C => "Elite Systems",
+        0x13 => "Electronic Arts",
+        0x18 => "Hudson Soft",
+        0x19 => "ITC Entertainment",
+        0x1A => "Yanoman",
+        0x1D => "Japan Clary",
+        0x1F => "Virgin Interactive",
+        0x24 => "PCM Complete",
+        0x25 => "San-X",
+        0x28 => "Kotobuki Systems",
+        0x29 => "Seta",
+        0x30 => "Infogrames",
+        0x31 => "Nintendo",
+        0x32 => "Bandai",
+        0x34 => "Konami",
+        0x35 => "HectorSoft",
+        0x38 => "Capcom",
+        0x39 => "Banpresto",
+        0x3C => "Entertainment Interactive",
+        0x3E => "Gremlin",
+        0x41 => "Ubisoft",
+        0x42 => "Atlus",
+        0x44 => "Malibu",
+        0x46 => "Angel",
+        0x47 => "Spectrum HoloByte",
+        0x49 => "Irem",
+        0x4A => "Virgin Interactive",
+        0x4D => "Malibu",
+        0x4F => "U.S. Gold",
+        0x50 => "Absolute",
+        0x51 => "Acclaim",
+        0x52 => "Activision",
+        0x53 => "American Sammy",
+        0x54 => "GameTek",
+        0x55 => "Park Place",
+        0x56 => "LJN",
+        0x57 => "Matchbox",
+        0x59 => "Milton Bradley",
+        0x5A => "Mindscape",
+        0x5B => "Romstar",
+        0x5C => "Naxat Soft",
+        0x5D => "Tradewest",
+        0x60 => "Titus",
+        0x61 => "Virgin Interactive",
+        0x67 => "Ocean",
+        0x69 => "Electronic Arts",
+        0x6E => "Elite Systems",
+        0x6F => "Electro Brain",
+        0x70 => "Infogrames",
+        0x71 => "Interplay",
+        0x72 => "Broderbund",
+        0x73 => "Sculptured Soft",
+        0x75 => "The Sales Curve",
+        0x78 => "THQ",
+        0x79 => "Accolade",
+        0x7A => "Triffix Entertainment",
+        0x7C => "MicroProse",
+        0x7F => "Kemco",
+        0x80 => "Misawa Entertainment",
+        0x83 => "Lozc",
+        0x86 => "Tokuma Shoten Intermedia",
+        0x8B => "Bullet-Proof Software",
+        0x8C => "Vic Tokai",
+        0x8E => "Ape",
+        0x8F => "I'Max",
+        0x91 => "Chunsoft",
+        0x92 => "Video System",
+        0x93 => "Tsuburaya Productions",
+        0x95 => "Varie",
+        0x96 => "Yonezawa/S'Pal",
+        0x97 => "Kaneko",
+        0x99 => "Arc",
+        0x9A => "Nihon Bussan",
+        0x9B => "Tecmo",
+        0x9C => "Imagineer",
+        0x9D => "Banpresto",
+        0x9F => "Nova",
+        0xA1 => "Hori Electric",
+        0xA2 => "Bandai",
+        0xA4 => "Konami",
+        0xA6 => "Kawada",
+        0xA7 => "Takara",
+        0xA9 => "Technos Japan",
+        0xAA => "Broderbund",
+        0xAC => "Toei Animation",
+        0xAD => "Toho",
+        0xAF => "Namco",
+        0xB0 => "Acclaim",
+        0xB1 => "ASCII or Nexsoft",
+        0xB2 => "Bandai",
+        0xB4 => "Square Enix",
+        0xB6 => "HAL Laboratory",
+        0xB7 => "SNK",
+        0xB9 => "Pony Canyon",
+        0xBA => "Culture Brain",
+        0xBB => "Sunsoft",
+        0xBD => "Sony Imagesoft",
+        0xBF => "Sammy",
+        0xC0 => "Taito",
+        0xC2 => "Kemco",
+        0xC3 => "Square",
+        0xC4 => "Tokuma Shoten Intermedia",
+        0xC5 => "Data East",
+        0xC6 => "Tonkin House",
+        0xC8 => "Koei",
+        0xC9 => "UFL",
+        0xCA => "Ultra",
+        0xCB => "Vap",
+        0xCC => "Use Corporation",
+        0xCD => "Meldac",
+        0xCE => "Pony Canyon",
+        0xCF => "Angel",
+        0xD0 => "Taito",
+        0xD1 => "Sofel",
+        0xD2 => "Quest",
+        0xD3 => "Sigma Enterprises",
+        0xD4 => "ASK Kodansha",
+        0xD6 => "Naxat Soft",
+        0xD7 => "Copya System",
+        0xD9 => "Banpresto",
+        0xDA => "Tomy",
+        0xDB => "LJN",
+        0xDD => "NCS",
+        0xDE => "Human",
+        0xDF => "Altron",
+        0xE0 => "Jaleco",
+        0xE1 => "Towa Chiki",
+        0xE2 => "Yutaka",
+        0xE3 => "Varie",
+        0xE5 => "Epoch",
+        0xE7 => "Athena",
+        0xE8 => "Asmik Ace Entertainment",
+        0xE9 => "Natsume",
+        0xEA => "King Records",
+        0xEB => "Atlus",
+        0xEC => "Epic/Sony Records",
+        0xEE => "IGS",
+        0xF0 => "A Wave",
+        0xF3 => "Extreme Entertainment",
+        0xFF => "LJN",
+        _ => return None,
+    };
+    Some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(title: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; HEADER_END];
+        rom[0x134..0x134 + title.len()].copy_from_slice(title);
+        rom
+    }
+
+    #[test]
+    fn test_titles_and_cgb_flag() {
+        let header = parse(&rom(b"TETRIS")).unwrap();
+        assert_eq!(header.title, "TETRIS");
+        assert_eq!(header.cgb, CgbFlag::Dmg);
+        assert_eq!(header.manufacturer, None);
+
+        // 11 characters title followed by a manufacturer code
+        let mut cgb = rom(b"POKEMON_SLVAAXE\xC0");
+        let header = parse(&cgb).unwrap();
+        assert_eq!(header.cgb, CgbFlag::Only);
+        assert_eq!(header.title, "POKEMON_SLV");
+        assert_eq!(header.manufacturer.as_deref(), Some("AAXE"));
+
+        cgb[0x13F] = b'a';
+        cgb[0x143] = 0x80;
+        let header = parse(&cgb).unwrap();
+        assert_eq!(header.cgb, CgbFlag::Compatible);
+        assert_eq!(header.title, "POKEMON_SLVaAXE");
+        assert_eq!(header.manufacturer, None);
+
+        assert_eq!(parse(&rom(b"\x01BAD")).unwrap().title, "?BAD");
+        assert!(parse(&[0; 0x14F]).is_err());
+    }
+
+    #[test]
+    fn test_sizes_and_codes() {
+        let mut rom = rom(b"GAME");
+        rom[0x147] = 0x13;
+        rom[0x148] = 0x05;
+        rom[0x149] = 0x03;
+        rom[0x14E] = 0x12;
+        rom[0x14F] = 0x34;
+        let header = parse(&rom).unwrap();
+        assert_eq!(header.rom_size, 64 * ROM_BANK_SIZE);
+        assert_eq!(header.ram_size, 4 * RAM_BANK_SIZE);
+        assert_eq!(header.global_checksum, 0x1234);
+        assert_eq!(header.mapper_name(), Some("MBC3"));
+        assert_eq!(header.cartridge_type_name(), "MBC3+RAM+BATTERY");
+        assert!(header.has_battery());
+
+        rom[0x147] = 0x20;
+        rom[0x148] = 0x52;
+        rom[0x149] = 0x01;
+        let header = parse(&rom).unwrap();
+        assert_eq!(header.rom_size, 72 * ROM_BANK_SIZE);
+        assert_eq!(header.ram_size, 0x800);
+        assert_eq!(header.mapper_name(), None);
+        assert!(!header.has_battery());
+    }
+
+    #[test]
+    fn test_sgb_flag_needs_new_licensee() {
+        let mut rom = rom(b"GAME");
+        rom[0x146] = 0x03;
+        rom[0x14B] = 0x01;
+        assert!(!parse(&rom).unwrap().sgb);
+        rom[0x14B] = 0x33;
+        rom[0x144..0x146].copy_from_slice(b"01");
+        let header = parse(&rom).unwrap();
+        assert!(header.sgb);
+        assert_eq!(header.licensee(), "Nintendo R&D1");
+
+        rom[0x14B] = 0x01;
+        assert_eq!(parse(&rom).unwrap().licensee(), "Nintendo");
+        rom[0x14B] = 0xFE;
+        assert_eq!(parse(&rom).unwrap().licensee(), "Unknown (0xfe)");
+    }
+}
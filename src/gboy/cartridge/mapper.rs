@@ -0,0 +1,163 @@
+use std::io;
+
+use super::super::state::{StateReader, StateWriter};
+use super::rtc::Rtc;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+/// value seen on unmapped or disabled regions
+pub static OPEN_BUS: u8 = 0xFF;
+
+/// Memory bank controller sitting between the cpu and the cartridge chips
+pub trait Mapper {
+    // whole rom image, as dumped
+    fn rom(&self) -> &[u8];
+
+    // read on 0x0000..=0x7FFF
+    fn read_rom(&self, address: usize) -> &u8;
+
+    // write on 0x0000..=0x7FFF, controller registers
+    fn write_rom(&mut self, address: usize, value: u8);
+
+    // read on 0xA000..=0xBFFF
+    fn read_ram(&self, address: usize) -> &u8;
+
+    // write on 0xA000..=0xBFFF
+    fn write_ram(&mut self, address: usize, value: u8);
+
+    // whole external ram, as persisted on battery saves
+    fn ram(&self) -> &[u8];
+
+    // restores external ram from a battery save
+    fn load_ram(&mut self, data: &[u8]);
+
+    // banking registers, ram and chips state for save states
+    fn save_state(&self, w: &mut StateWriter);
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error>;
+
+    // walks time based chips up to the cpu clock_cycles count
+    fn tick(&mut self, _clock_cycles: usize) {}
+
+    // real time clock, on carts having one
+    fn rtc(&mut self) -> Option<&mut Rtc> {
+        None
+    }
+}
+
+/// bank controllers emulated, as wired for a cartridge type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    RomOnly,
+    Mbc1,
+    Mbc2,
+    Mbc3 { timer: bool },
+    Mbc5 { rumble: bool },
+}
+
+/// bank controller for cartridge type code at 0x147, None when unsupported
+pub fn kind(cartridge_type: u8) -> Option<Kind> {
+    let kind = match cartridge_type {
+        0x00 | 0x08 | 0x09 => Kind::RomOnly,
+        0x01..=0x03 => Kind::Mbc1,
+        0x05 | 0x06 => Kind::Mbc2,
+        0x0F | 0x10 => Kind::Mbc3 { timer: true },
+        0x11..=0x13 => Kind::Mbc3 { timer: false },
+        0x19..=0x1B => Kind::Mbc5 { rumble: false },
+        0x1C..=0x1E => Kind::Mbc5 { rumble: true },
+        _ => return None,
+    };
+    Some(kind)
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::RomOnly => "None",
+            Kind::Mbc1 => "MBC1",
+            Kind::Mbc2 => "MBC2",
+            Kind::Mbc3 { .. } => "MBC3",
+            Kind::Mbc5 { .. } => "MBC5",
+        }
+    }
+}
+
+/// byte on rom bank for an address, bank numbers wrap around the rom size
+/// as the unused upper bank lines aren't wired
+pub fn rom_byte(rom: &[u8], bank: usize, address: usize) -> &u8 {
+    if rom.is_empty() {
+        return &OPEN_BUS;
+    }
+    &rom[(bank * ROM_BANK_SIZE + (address & (ROM_BANK_SIZE - 1))) % rom.len()]
+}
+
+/// copies a save over ram, saves from other emulators may be padded or short
+pub fn copy_ram(ram: &mut [u8], data: &[u8]) {
+    let len = ram.len().min(data.len());
+    ram[..len].copy_from_slice(&data[..len]);
+}
+
+/// offset on ram bank for an address, None when there's no ram at all
+pub fn ram_offset(ram: &[u8], bank: usize, address: usize) -> Option<usize> {
+    if ram.is_empty() {
+        return None;
+    }
+    Some((bank * RAM_BANK_SIZE + (address & (RAM_BANK_SIZE - 1))) % ram.len())
+}
+
+/// 32KiB rom wired straight to the bus, optionally with 8KiB ram
+pub struct RomOnly {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+}
+
+pub fn rom_only(rom: Vec<u8>, ram_size: usize) -> RomOnly {
+    RomOnly {
+        rom,
+        ram: vec![0; ram_size],
+    }
+}
+
+impl Mapper for RomOnly {
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn read_rom(&self, address: usize) -> &u8 {
+        rom_byte(&self.rom, address / ROM_BANK_SIZE, address)
+    }
+
+    fn write_rom(&mut self, address: usize, value: u8) {
+        trace!("rom only write ignored {:#x} => {:#x}", address, value);
+    }
+
+    fn read_ram(&self, address: usize) -> &u8 {
+        match ram_offset(&self.ram, 0, address) {
+            Some(o) => &self.ram[o],
+            None => &OPEN_BUS,
+        }
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8) {
+        if let Some(o) = ram_offset(&self.ram, 0, address) {
+            self.ram[o] = value;
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        copy_ram(&mut self.ram, data);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        r.bytes_into(&mut self.ram)
+    }
+}
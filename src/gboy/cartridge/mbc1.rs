@@ -0,0 +1,218 @@
+use std::io;
+
+use super::super::state::{StateReader, StateWriter};
+use super::mapper::*;
+use super::NINTENDO_LOGO;
+
+/// MBC1, up to 2MiB rom and 32KiB ram
+///
+/// BANK1 holds the lower 5 bits of the rom bank, BANK2 2 more bits used either as
+/// upper rom bank bits or as ram bank, MODE picks whether BANK2 also applies to
+/// 0x0000..=0x3FFF and ram. Multicarts (MBC1M) wire only 4 bits of BANK1.
+pub struct Mbc1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    bank1: u8,
+    bank2: u8,
+    mode: u8,
+    multicart: bool,
+}
+
+pub fn new(rom: Vec<u8>, ram_size: usize) -> Mbc1 {
+    let multicart = is_multicart(&rom);
+    if multicart {
+        info!("MBC1M multicart detected");
+    }
+    Mbc1 {
+        rom,
+        ram: vec![0; ram_size],
+        ram_enabled: false,
+        bank1: 0x1,
+        bank2: 0x0,
+        mode: 0x0,
+        multicart,
+    }
+}
+
+/// multicarts are 1MiB roms with a second game, and its logo, starting on bank 0x10
+fn is_multicart(rom: &[u8]) -> bool {
+    let logo = 0x10 * ROM_BANK_SIZE + 0x104;
+    rom.len() == 64 * ROM_BANK_SIZE && rom[logo..logo + NINTENDO_LOGO.len()] == NINTENDO_LOGO[..]
+}
+
+impl Mbc1 {
+    fn bank2_shift(&self) -> usize {
+        if self.multicart { 4 } else { 5 }
+    }
+
+    fn bank1_mask(&self) -> u8 {
+        if self.multicart { 0x0F } else { 0x1F }
+    }
+
+    fn low_rom_bank(&self) -> usize {
+        if self.mode == 0x1 {
+            (self.bank2 as usize) << self.bank2_shift()
+        } else {
+            0x0
+        }
+    }
+
+    fn high_rom_bank(&self) -> usize {
+        ((self.bank2 as usize) << self.bank2_shift()) | (self.bank1 & self.bank1_mask()) as usize
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.mode == 0x1 { self.bank2 as usize } else { 0x0 }
+    }
+}
+
+impl Mapper for Mbc1 {
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn read_rom(&self, address: usize) -> &u8 {
+        let bank = if address < ROM_BANK_SIZE { self.low_rom_bank() } else { self.high_rom_bank() };
+        rom_byte(&self.rom, bank, address)
+    }
+
+    fn write_rom(&mut self, address: usize, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // bank 0 can't be selected, checked before multicart masking
+                self.bank1 = value & 0x1F;
+                if self.bank1 == 0x0 {
+                    self.bank1 = 0x1;
+                }
+            },
+            0x4000..=0x5FFF => self.bank2 = value & 0b11,
+            _ => self.mode = value & 0b1,
+        }
+        trace!("mbc1 write {:#x} => {:#x} bank1:{:#x} bank2:{:#x} mode:{}", address, value, self.bank1, self.bank2, self.mode);
+    }
+
+    fn read_ram(&self, address: usize) -> &u8 {
+        if !self.ram_enabled {
+            return &OPEN_BUS;
+        }
+        match ram_offset(&self.ram, self.ram_bank(), address) {
+            Some(o) => &self.ram[o],
+            None => &OPEN_BUS,
+        }
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        if let Some(o) = ram_offset(&self.ram, self.ram_bank(), address) {
+            self.ram[o] = value;
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        copy_ram(&mut self.ram, data);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        w.bool(self.ram_enabled);
+        w.u8(self.bank1);
+        w.u8(self.bank2);
+        w.u8(self.mode);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        r.bytes_into(&mut self.ram)?;
+        self.ram_enabled = r.bool()?;
+        self.bank1 = r.u8()?;
+        self.bank2 = r.u8()?;
+        self.mode = r.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// rom whose banks start with their own number
+    fn rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_bank_0_selects_bank_1() {
+        let mut mbc = new(rom(4), 0);
+        mbc.write_rom(0x2000, 0x0);
+        assert_eq!(*mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x2000, 0x3);
+        assert_eq!(*mbc.read_rom(0x4000), 3);
+        // only the lower 5 bits count, 0x20 is bank 0 too
+        mbc.write_rom(0x2000, 0x20);
+        assert_eq!(*mbc.read_rom(0x4000), 1);
+    }
+
+    #[test]
+    fn test_mode_select() {
+        let mut mbc = new(rom(128), 4 * RAM_BANK_SIZE);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x2000, 0x2);
+        mbc.write_rom(0x4000, 0x1);
+        assert_eq!(*mbc.read_rom(0x4000), 0x22);
+        assert_eq!(*mbc.read_rom(0x0000), 0x0);
+        mbc.write_ram(0xA000, 0x11);
+
+        mbc.write_rom(0x6000, 0x1);
+        assert_eq!(*mbc.read_rom(0x0000), 0x20);
+        assert_eq!(*mbc.read_rom(0x4000), 0x22);
+        assert_eq!(*mbc.read_ram(0xA000), 0x0);
+        mbc.write_ram(0xA000, 0x22);
+
+        mbc.write_rom(0x6000, 0x0);
+        assert_eq!(*mbc.read_ram(0xA000), 0x11);
+        assert_eq!(mbc.ram()[RAM_BANK_SIZE], 0x22);
+    }
+
+    #[test]
+    fn test_ram_enable() {
+        let mut mbc = new(rom(4), RAM_BANK_SIZE);
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(*mbc.read_ram(0xA000), OPEN_BUS);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(*mbc.read_ram(0xA000), 0x42);
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(*mbc.read_ram(0xA000), OPEN_BUS);
+        assert_eq!(mbc.ram()[0], 0x42);
+    }
+
+    #[test]
+    fn test_multicart_detection() {
+        let mut image = rom(64);
+        let logo = 0x10 * ROM_BANK_SIZE + 0x104;
+        image[logo..logo + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        let mut mbc = new(image.clone(), 0);
+        assert!(mbc.multicart);
+        // BANK2 lands on bit 4, BANK1 keeps 4 bits
+        mbc.write_rom(0x4000, 0x1);
+        mbc.write_rom(0x2000, 0x12);
+        assert_eq!(*mbc.read_rom(0x4000), 0x12);
+        mbc.write_rom(0x6000, 0x1);
+        assert_eq!(*mbc.read_rom(0x0000), 0x10);
+
+        image[logo] = 0x0;
+        assert!(!new(image, 0).multicart);
+        assert!(!new(rom(128), 0).multicart);
+    }
+}
@@ -0,0 +1,131 @@
+use std::io;
+
+use super::super::state::{StateReader, StateWriter};
+use super::mapper::*;
+
+const RAM_SIZE: usize = 0x200;
+
+/// MBC2, up to 256KiB rom and a built-in 512x4 bits ram
+///
+/// A single register range on 0x0000..=0x3FFF, address bit 8 picks between
+/// ram enable (clear) and rom bank (set). Only the lower nibble of ram is
+/// wired, the upper one reads back as 1s.
+pub struct Mbc2 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+pub fn new(rom: Vec<u8>) -> Mbc2 {
+    Mbc2 {
+        rom,
+        ram: vec![0xF0; RAM_SIZE],
+        ram_enabled: false,
+        rom_bank: 0x1,
+    }
+}
+
+impl Mapper for Mbc2 {
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn read_rom(&self, address: usize) -> &u8 {
+        let bank = if address < ROM_BANK_SIZE { 0x0 } else { self.rom_bank as usize };
+        rom_byte(&self.rom, bank, address)
+    }
+
+    fn write_rom(&mut self, address: usize, value: u8) {
+        match address {
+            0x0000..=0x3FFF if address & 0x100 == 0x0 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x0000..=0x3FFF => {
+                self.rom_bank = value & 0x0F;
+                if self.rom_bank == 0x0 {
+                    self.rom_bank = 0x1;
+                }
+            },
+            _ => {},
+        }
+        trace!("mbc2 write {:#x} => {:#x} rom_bank:{:#x}", address, value, self.rom_bank);
+    }
+
+    fn read_ram(&self, address: usize) -> &u8 {
+        if !self.ram_enabled {
+            return &OPEN_BUS;
+        }
+        &self.ram[address & (RAM_SIZE - 1)]
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8) {
+        if self.ram_enabled {
+            self.ram[address & (RAM_SIZE - 1)] = 0xF0 | value;
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        copy_ram(&mut self.ram, data);
+        for v in self.ram.iter_mut() {
+            *v |= 0xF0;
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        w.bool(self.ram_enabled);
+        w.u8(self.rom_bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        r.bytes_into(&mut self.ram)?;
+        self.ram_enabled = r.bool()?;
+        self.rom_bank = r.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// rom whose banks start with their own number
+    fn rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_address_bit_8_picks_register() {
+        let mut mbc = new(rom(16));
+        // bit 8 clear enables ram whatever the address
+        mbc.write_rom(0x2000, 0x0A);
+        assert_eq!(*mbc.read_rom(0x4000), 1);
+        mbc.write_ram(0xA000, 0x5);
+        assert_eq!(*mbc.read_ram(0xA000), 0xF5);
+
+        mbc.write_rom(0x0100, 0x3);
+        assert_eq!(*mbc.read_rom(0x4000), 3);
+        mbc.write_rom(0x2100, 0x0);
+        assert_eq!(*mbc.read_rom(0x4000), 1);
+        assert_eq!(*mbc.read_ram(0xA000), 0xF5);
+    }
+
+    #[test]
+    fn test_ram_is_512_nibbles() {
+        let mut mbc = new(rom(2));
+        assert_eq!(*mbc.read_ram(0xA000), OPEN_BUS);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA001, 0xAB);
+        // echoed every 512 bytes, upper nibble reads 1s
+        assert_eq!(*mbc.read_ram(0xA201), 0xFB);
+        mbc.load_ram(&[0x01, 0x02]);
+        assert_eq!(mbc.ram()[..2], [0xF1, 0xF2]);
+    }
+}
@@ -0,0 +1,177 @@
+use std::io;
+
+use super::super::state::{StateReader, StateWriter};
+use super::mapper::*;
+use super::rtc::Rtc;
+
+/// MBC3, up to 2MiB rom and 32KiB ram (MBC30 up to 4MiB rom and 64KiB ram)
+///
+/// 0x4000..=0x5FFF selects either a ram bank (0x00..=0x07) or one of the
+/// clock registers (0x08..=0x0C) on 0xA000..=0xBFFF.
+pub struct Mbc3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+    rtc: Option<Rtc>,
+}
+
+pub fn new(rom: Vec<u8>, ram_size: usize, has_timer: bool) -> Mbc3 {
+    Mbc3 {
+        rom,
+        ram: vec![0; ram_size],
+        ram_enabled: false,
+        rom_bank: 0x1,
+        ram_bank: 0x0,
+        rtc: if has_timer { Some(Rtc::default()) } else { None },
+    }
+}
+
+impl Mapper for Mbc3 {
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn read_rom(&self, address: usize) -> &u8 {
+        let bank = if address < ROM_BANK_SIZE { 0x0 } else { self.rom_bank as usize };
+        rom_byte(&self.rom, bank, address)
+    }
+
+    fn write_rom(&mut self, address: usize, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                self.rom_bank = value;
+                if self.rom_bank == 0x0 {
+                    self.rom_bank = 0x1;
+                }
+            },
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write_latch(value);
+                }
+            },
+        }
+        trace!("mbc3 write {:#x} => {:#x} rom_bank:{:#x} ram_bank:{:#x}", address, value, self.rom_bank, self.ram_bank);
+    }
+
+    fn read_ram(&self, address: usize) -> &u8 {
+        if !self.ram_enabled {
+            return &OPEN_BUS;
+        }
+        match (self.ram_bank, &self.rtc) {
+            (0x08..=0x0C, Some(rtc)) => return rtc.read(self.ram_bank),
+            (0x08..=0x0F, _) => return &OPEN_BUS,
+            _ => {},
+        }
+        match ram_offset(&self.ram, self.ram_bank as usize, address) {
+            Some(o) => &self.ram[o],
+            None => &OPEN_BUS,
+        }
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        match (self.ram_bank, self.rtc.as_mut()) {
+            (0x08..=0x0C, Some(rtc)) => return rtc.write(self.ram_bank, value),
+            (0x08..=0x0F, _) => return,
+            _ => {},
+        }
+        if let Some(o) = ram_offset(&self.ram, self.ram_bank as usize, address) {
+            self.ram[o] = value;
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        copy_ram(&mut self.ram, data);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        w.bool(self.ram_enabled);
+        w.u8(self.rom_bank);
+        w.u8(self.ram_bank);
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        r.bytes_into(&mut self.ram)?;
+        self.ram_enabled = r.bool()?;
+        self.rom_bank = r.u8()?;
+        self.ram_bank = r.u8()?;
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load_state(r)?;
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, clock_cycles: usize) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.tick(clock_cycles);
+        }
+    }
+
+    fn rtc(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// rom whose banks start with their own number
+    fn rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_rom_bank_takes_7_bits() {
+        let mut mbc = new(rom(128), 0, false);
+        mbc.write_rom(0x2000, 0x0);
+        assert_eq!(*mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x2000, 0x7F);
+        assert_eq!(*mbc.read_rom(0x4000), 0x7F);
+        assert_eq!(*mbc.read_rom(0x0000), 0x0);
+    }
+
+    #[test]
+    fn test_ram_banks_and_clock_registers() {
+        let mut mbc = new(rom(4), 4 * RAM_BANK_SIZE, true);
+        mbc.write_ram(0xA000, 0x11);
+        assert_eq!(*mbc.read_ram(0xA000), OPEN_BUS);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x2);
+        mbc.write_ram(0xA000, 0x22);
+        assert_eq!(mbc.ram()[2 * RAM_BANK_SIZE], 0x22);
+
+        // seconds register, time passing is seen once latched
+        mbc.write_rom(0x4000, 0x08);
+        mbc.write_ram(0xA000, 0x05);
+        mbc.tick(super::super::rtc::CYCLES_PER_SECOND);
+        assert_eq!(*mbc.read_ram(0xA000), 0x05);
+        mbc.write_rom(0x6000, 0x0);
+        mbc.write_rom(0x6000, 0x1);
+        assert_eq!(*mbc.read_ram(0xA000), 0x06);
+        assert_eq!(mbc.ram()[2 * RAM_BANK_SIZE], 0x22);
+
+        let mut plain = new(rom(4), RAM_BANK_SIZE, false);
+        plain.write_rom(0x0000, 0x0A);
+        plain.write_rom(0x4000, 0x09);
+        assert_eq!(*plain.read_ram(0xA000), OPEN_BUS);
+    }
+}
@@ -0,0 +1,151 @@
+use std::io;
+
+use super::super::state::{StateReader, StateWriter};
+use super::mapper::*;
+
+/// MBC5, up to 8MiB rom and 128KiB ram
+///
+/// 9 bits rom bank split over 0x2000..=0x2FFF (lower 8) and 0x3000..=0x3FFF
+/// (bit 8), bank 0 can be mapped on 0x4000..=0x7FFF. On rumble carts bit 3
+/// of the ram bank register drives the motor instead.
+pub struct Mbc5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u16,
+    ram_bank: u8,
+    has_rumble: bool,
+    rumble: bool,
+}
+
+pub fn new(rom: Vec<u8>, ram_size: usize, has_rumble: bool) -> Mbc5 {
+    Mbc5 {
+        rom,
+        ram: vec![0; ram_size],
+        ram_enabled: false,
+        rom_bank: 0x1,
+        ram_bank: 0x0,
+        has_rumble,
+        rumble: false,
+    }
+}
+
+impl Mapper for Mbc5 {
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn read_rom(&self, address: usize) -> &u8 {
+        let bank = if address < ROM_BANK_SIZE { 0x0 } else { self.rom_bank as usize };
+        rom_byte(&self.rom, bank, address)
+    }
+
+    fn write_rom(&mut self, address: usize, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 0b1) << 8),
+            0x4000..=0x5FFF if self.has_rumble => {
+                let rumble = value & 0b1000 != 0x0;
+                if rumble != self.rumble {
+                    debug!("rumble {}", if rumble { "on" } else { "off" });
+                }
+                self.rumble = rumble;
+                self.ram_bank = value & 0x07;
+            },
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => {},
+        }
+        trace!("mbc5 write {:#x} => {:#x} rom_bank:{:#x} ram_bank:{:#x}", address, value, self.rom_bank, self.ram_bank);
+    }
+
+    fn read_ram(&self, address: usize) -> &u8 {
+        if !self.ram_enabled {
+            return &OPEN_BUS;
+        }
+        match ram_offset(&self.ram, self.ram_bank as usize, address) {
+            Some(o) => &self.ram[o],
+            None => &OPEN_BUS,
+        }
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        if let Some(o) = ram_offset(&self.ram, self.ram_bank as usize, address) {
+            self.ram[o] = value;
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        copy_ram(&mut self.ram, data);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        w.bool(self.ram_enabled);
+        w.u16(self.rom_bank);
+        w.u8(self.ram_bank);
+        w.bool(self.rumble);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        r.bytes_into(&mut self.ram)?;
+        self.ram_enabled = r.bool()?;
+        self.rom_bank = r.u16()?;
+        self.ram_bank = r.u8()?;
+        self.rumble = r.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// rom whose banks start with their own number
+    fn rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_9_bits_rom_bank() {
+        let mut mbc = new(rom(512), 0, false);
+        mbc.write_rom(0x2000, 0x0);
+        assert_eq!(*mbc.read_rom(0x4000), 0);
+        mbc.write_rom(0x2000, 0x05);
+        mbc.write_rom(0x3000, 0x1);
+        assert_eq!(mbc.rom_bank, 0x105);
+        assert_eq!(*mbc.read_rom(0x4000), 0x05);
+        assert_eq!(mbc.rom[0x105 * ROM_BANK_SIZE + 1], 0x0);
+        mbc.write_rom(0x3000, 0x0);
+        assert_eq!(mbc.rom_bank, 0x005);
+    }
+
+    #[test]
+    fn test_rumble_takes_ram_bank_bit_3() {
+        let mut mbc = new(rom(4), 16 * RAM_BANK_SIZE, false);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0A);
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.ram()[0x0A * RAM_BANK_SIZE], 0x42);
+
+        let mut rumble = new(rom(4), 16 * RAM_BANK_SIZE, true);
+        rumble.write_rom(0x0000, 0x0A);
+        rumble.write_rom(0x4000, 0x0A);
+        assert!(rumble.rumble);
+        rumble.write_ram(0xA000, 0x42);
+        assert_eq!(rumble.ram()[0x02 * RAM_BANK_SIZE], 0x42);
+        rumble.write_rom(0x4000, 0x02);
+        assert!(!rumble.rumble);
+    }
+}
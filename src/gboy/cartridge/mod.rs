@@ -0,0 +1,177 @@
+pub mod header;
+pub mod mapper;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+pub mod rtc;
+mod validation;
+
+pub use header::{CgbFlag, Header};
+pub use validation::Validation;
+use header::cart_type_name;
+use mapper::*;
+use std::io;
+
+use super::state::{StateReader, StateWriter};
+
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83,
+    0x00, 0x0C, 0x00, 0x0D, 0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E,
+    0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99, 0xBB, 0xBB, 0x67, 0x63,
+    0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+pub struct Cartridge {
+    header: Header,
+    mapper: Box<dyn Mapper>,
+}
+
+pub fn new(gamerom: Vec<u8>) -> Result<Cartridge, io::Error> {
+    let header = header::parse(&gamerom)?;
+    let cart_type = header.cartridge_type;
+    let ram_size = header.ram_size;
+    debug!("cartridge type:{:#x} ram_size:{:#x}", cart_type, ram_size);
+
+    let mapper: Box<dyn Mapper> = match mapper::kind(cart_type) {
+        Some(Kind::RomOnly) => Box::new(mapper::rom_only(gamerom, ram_size)),
+        Some(Kind::Mbc1) => Box::new(mbc1::new(gamerom, ram_size)),
+        Some(Kind::Mbc2) => Box::new(mbc2::new(gamerom)),
+        Some(Kind::Mbc3 { timer }) => Box::new(mbc3::new(gamerom, ram_size, timer)),
+        Some(Kind::Mbc5 { rumble }) => Box::new(mbc5::new(gamerom, ram_size, rumble)),
+        None => {
+            let name = cart_type_name(cart_type).unwrap_or("unknown");
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported mapper 0x{:02X} ({})", cart_type, name),
+            ));
+        },
+    };
+    Ok(Cartridge {
+        header,
+        mapper,
+    })
+}
+
+/// cartridge for looking at a rom without running it, any cartridge type is
+/// accepted and wired as rom only
+pub fn inspect(gamerom: Vec<u8>) -> Result<Cartridge, io::Error> {
+    let header = header::parse(&gamerom)?;
+    Ok(Cartridge {
+        mapper: Box::new(mapper::rom_only(gamerom, header.ram_size)),
+        header,
+    })
+}
+
+impl Cartridge {
+
+    pub fn rom_len(&mut self) -> usize {
+        self.mapper.rom().len()
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn crc32(&mut self) -> u32 {
+        crc32fast::hash(self.mapper.rom())
+    }
+
+    pub fn sha1(&mut self) -> String {
+        sha1::Sha1::from(self.mapper.rom()).digest().to_string()
+    }
+
+    pub fn tick(&mut self, clock_cycles: usize) {
+        self.mapper.tick(clock_cycles);
+    }
+
+    /// rtc state in the BGB/VBA-M footer format, None without a clock
+    pub fn rtc_footer(&mut self) -> Option<Vec<u8>> {
+        self.mapper.rtc().map(|rtc| rtc.footer())
+    }
+
+    pub fn load_rtc_footer(&mut self, footer: &[u8]) -> Result<(), io::Error> {
+        match self.mapper.rtc() {
+            Some(rtc) => rtc.load_footer(footer),
+            None => Ok(()),
+        }
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        self.mapper.save_state(w);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        self.mapper.load_state(r)
+    }
+
+    pub fn has_battery(&mut self) -> bool {
+        self.header.has_battery()
+    }
+
+    /// .sav content, external ram followed by the rtc footer when there's a clock
+    pub fn battery_save(&mut self) -> Vec<u8> {
+        let mut save = self.mapper.ram().to_vec();
+        if let Some(footer) = self.rtc_footer() {
+            save.extend_from_slice(&footer);
+        }
+        save
+    }
+
+    /// external ram and clock registers, the battery save without its timestamp
+    pub fn battery_contents(&mut self) -> Vec<u8> {
+        let mut contents = self.mapper.ram().to_vec();
+        if let Some(rtc) = self.mapper.rtc() {
+            contents.extend_from_slice(&rtc.footer_registers());
+        }
+        contents
+    }
+
+    pub fn load_battery_save(&mut self, save: &[u8]) -> Result<(), io::Error> {
+        let ram_len = self.mapper.ram().len();
+        let (ram, footer) = save.split_at(ram_len.min(save.len()));
+        if ram.len() < ram_len {
+            warn!("battery save shorter than cartridge ram, {} of {} bytes", ram.len(), ram_len);
+        }
+        self.mapper.load_ram(ram);
+        if !footer.is_empty() {
+            self.load_rtc_footer(footer)?;
+        }
+        Ok(())
+    }
+
+    /// raw rom byte, regardless of banking
+    pub fn read(&mut self, address: usize) -> u8 {
+        self.mapper.rom()[address]
+    }
+
+    /// cpu read on 0x0000..=0x7FFF
+    pub fn read_rom(&self, address: usize) -> &u8 {
+        self.mapper.read_rom(address)
+    }
+
+    /// cpu read on 0xA000..=0xBFFF
+    pub fn read_ram(&self, address: usize) -> &u8 {
+        self.mapper.read_ram(address)
+    }
+
+    /// cpu write on 0x0000..=0x7FFF or 0xA000..=0xBFFF
+    pub fn write(&mut self, address: usize, value: u8) {
+        match address {
+            0x0000..=0x7FFF => self.mapper.write_rom(address, value),
+            _ => self.mapper.write_ram(address, value),
+        }
+    }
+
+    pub fn header_checksum(&mut self) -> u8 {
+        self.header.header_checksum
+    }
+
+    pub fn calculate_header_checksum(&mut self) -> u8 {
+        let mut x: u8 = 0;
+        for i in 0x134..=0x14C {
+            x = x.wrapping_sub(self.read(i)).wrapping_sub(1);
+        }
+        x
+    }
+}
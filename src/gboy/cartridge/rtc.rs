@@ -0,0 +1,287 @@
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::super::state::{StateReader, StateWriter};
+
+/// cpu clock cycles per rtc second
+pub const CYCLES_PER_SECOND: usize = 4_194_304;
+/// BGB/VBA-M save footer, registers and latched registers as u32 then a u64 timestamp
+pub const FOOTER_SIZE: usize = 48;
+/// older VBA-M footer, with a u32 timestamp
+const SHORT_FOOTER_SIZE: usize = 44;
+
+const SECONDS: usize = 0x0;
+const MINUTES: usize = 0x1;
+const HOURS: usize = 0x2;
+const DAY_LOW: usize = 0x3;
+const DAY_HIGH: usize = 0x4;
+
+const DAY_HIGH_BIT: u8 = 0b0000_0001;
+const HALT: u8 = 0b0100_0000;
+const DAY_CARRY: u8 = 0b1000_0000;
+
+/// MBC3 real time clock
+///
+/// registers are S, M, H, DL and DH (day bit 8, halt and day carry), the cpu
+/// only sees a copy of them taken by writing 0x00 then 0x01 on 0x6000..=0x7FFF
+#[derive(Default)]
+pub struct Rtc {
+    registers: [u8; 5],
+    latched: [u8; 5],
+    latch: u8,
+    cycles: usize,
+    last_clock: usize,
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl Rtc {
+    /// clock as it is right now, unlike the footer no wall clock time is kept
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.registers);
+        w.bytes(&self.latched);
+        w.u8(self.latch);
+        w.usize(self.cycles);
+        w.usize(self.last_clock);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        r.bytes_into(&mut self.registers)?;
+        r.bytes_into(&mut self.latched)?;
+        self.latch = r.u8()?;
+        self.cycles = r.usize()?;
+        self.last_clock = r.usize()?;
+        Ok(())
+    }
+
+    /// walks the clock up to the cpu clock_cycles count
+    pub fn tick(&mut self, clock_cycles: usize) {
+        let elapsed = clock_cycles.saturating_sub(self.last_clock);
+        self.last_clock = clock_cycles;
+        if self.halted() {
+            return;
+        }
+        self.cycles += elapsed;
+        let seconds = self.cycles / CYCLES_PER_SECOND;
+        self.cycles %= CYCLES_PER_SECOND;
+        self.advance(seconds as u64);
+    }
+
+    fn halted(&self) -> bool {
+        self.registers[DAY_HIGH] & HALT != 0x0
+    }
+
+    fn in_range(&self) -> bool {
+        let r = &self.registers;
+        r[SECONDS] < 60 && r[MINUTES] < 60 && r[HOURS] < 24
+    }
+
+    /// runs the clock for some seconds, counting days over 511 as a day carry
+    fn advance(&mut self, mut seconds: u64) {
+        // counters set out of range only carry once they wrap, walk them back in range
+        while seconds > 0 && !self.in_range() {
+            self.step();
+            seconds -= 1;
+        }
+        if seconds == 0 {
+            return;
+        }
+        let r = &mut self.registers;
+        let days = ((r[DAY_HIGH] & DAY_HIGH_BIT) as u64) << 8 | r[DAY_LOW] as u64;
+        let total = days * 86400 + r[HOURS] as u64 * 3600 + r[MINUTES] as u64 * 60 + r[SECONDS] as u64 + seconds;
+        let days = total / 86400;
+        r[SECONDS] = (total % 60) as u8;
+        r[MINUTES] = (total / 60 % 60) as u8;
+        r[HOURS] = (total / 3600 % 24) as u8;
+        r[DAY_LOW] = days as u8;
+        r[DAY_HIGH] = (r[DAY_HIGH] & !DAY_HIGH_BIT) | ((days >> 8) as u8 & DAY_HIGH_BIT);
+        if days > 0x1FF {
+            r[DAY_HIGH] |= DAY_CARRY;
+        }
+    }
+
+    /// one second, counters wrap at their bit width when set out of range
+    fn step(&mut self) {
+        let r = &mut self.registers;
+        r[SECONDS] = (r[SECONDS] + 1) & 0x3F;
+        if r[SECONDS] != 60 {
+            return;
+        }
+        r[SECONDS] = 0;
+        r[MINUTES] = (r[MINUTES] + 1) & 0x3F;
+        if r[MINUTES] != 60 {
+            return;
+        }
+        r[MINUTES] = 0;
+        r[HOURS] = (r[HOURS] + 1) & 0x1F;
+        if r[HOURS] != 24 {
+            return;
+        }
+        r[HOURS] = 0;
+        r[DAY_LOW] = r[DAY_LOW].wrapping_add(1);
+        if r[DAY_LOW] == 0x0 {
+            if r[DAY_HIGH] & DAY_HIGH_BIT != 0x0 {
+                r[DAY_HIGH] = (r[DAY_HIGH] & !DAY_HIGH_BIT) | DAY_CARRY;
+            } else {
+                r[DAY_HIGH] |= DAY_HIGH_BIT;
+            }
+        }
+    }
+
+    /// write on 0x6000..=0x7FFF
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch == 0x0 && value == 0x1 {
+            self.latched = self.registers;
+            trace!("rtc latched {:?}", self.latched);
+        }
+        self.latch = value;
+    }
+
+    /// read on 0xA000..=0xBFFF with register 0x08..=0x0C selected
+    pub fn read(&self, register: u8) -> &u8 {
+        &self.latched[(register - 0x08) as usize]
+    }
+
+    /// write on 0xA000..=0xBFFF with register 0x08..=0x0C selected
+    pub fn write(&mut self, register: u8, value: u8) {
+        let index = (register - 0x08) as usize;
+        self.registers[index] = match index {
+            SECONDS => {
+                // writing seconds resets the sub-second divider
+                self.cycles = 0;
+                value & 0x3F
+            },
+            MINUTES => value & 0x3F,
+            HOURS => value & 0x1F,
+            DAY_LOW => value,
+            _ => value & (DAY_HIGH_BIT | HALT | DAY_CARRY),
+        };
+        // the cpu reads back what it just wrote
+        self.latched[index] = self.registers[index];
+    }
+
+    /// footer appended to the battery save
+    pub fn footer(&self) -> Vec<u8> {
+        let mut footer = self.footer_registers();
+        footer.extend_from_slice(&unix_time().to_le_bytes());
+        footer
+    }
+
+    /// footer up to the timestamp, what tells whether the clock changed
+    pub fn footer_registers(&self) -> Vec<u8> {
+        let mut footer = Vec::with_capacity(FOOTER_SIZE);
+        for r in self.registers.iter().chain(self.latched.iter()) {
+            footer.extend_from_slice(&(*r as u32).to_le_bytes());
+        }
+        footer
+    }
+
+    /// restores a footer, catching up with the real time elapsed since it was written
+    pub fn load_footer(&mut self, footer: &[u8]) -> Result<(), io::Error> {
+        let timestamp = match footer.len() {
+            FOOTER_SIZE => {
+                let mut t = [0u8; 8];
+                t.copy_from_slice(&footer[40..48]);
+                u64::from_le_bytes(t)
+            },
+            SHORT_FOOTER_SIZE => {
+                let mut t = [0u8; 4];
+                t.copy_from_slice(&footer[40..44]);
+                u32::from_le_bytes(t) as u64
+            },
+            n => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid rtc footer size {}", n))),
+        };
+        for i in 0..5 {
+            self.registers[i] = footer[i * 4];
+            self.latched[i] = footer[20 + i * 4];
+        }
+        let elapsed = unix_time().saturating_sub(timestamp);
+        debug!("rtc restored {:?}, {} seconds elapsed", self.registers, elapsed);
+        if !self.halted() {
+            self.advance(elapsed);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latched(rtc: &mut Rtc) -> [u8; 5] {
+        rtc.write_latch(0x0);
+        rtc.write_latch(0x1);
+        rtc.latched
+    }
+
+    #[test]
+    fn test_latch_takes_a_copy() {
+        let mut rtc = Rtc::default();
+        rtc.tick(3 * CYCLES_PER_SECOND);
+        assert_eq!(*rtc.read(0x08), 0);
+        assert_eq!(latched(&mut rtc)[SECONDS], 3);
+        rtc.tick(5 * CYCLES_PER_SECOND);
+        // only a 0x00 then 0x01 sequence latches again
+        rtc.write_latch(0x1);
+        assert_eq!(*rtc.read(0x08), 3);
+        assert_eq!(latched(&mut rtc)[SECONDS], 5);
+    }
+
+    #[test]
+    fn test_halt_stops_the_clock() {
+        let mut rtc = Rtc::default();
+        rtc.write(0x0C, HALT);
+        rtc.tick(10 * CYCLES_PER_SECOND);
+        assert_eq!(latched(&mut rtc)[SECONDS], 0);
+        rtc.write(0x0C, 0x0);
+        rtc.tick(12 * CYCLES_PER_SECOND);
+        assert_eq!(latched(&mut rtc)[SECONDS], 2);
+    }
+
+    #[test]
+    fn test_catch_up_carries_into_days() {
+        let mut rtc = Rtc::default();
+        rtc.write(0x0A, 23);
+        rtc.write(0x09, 59);
+        rtc.write(0x08, 59);
+        rtc.advance(2);
+        assert_eq!(rtc.registers, [1, 0, 0, 1, 0]);
+
+        rtc.write(0x0B, 0xFF);
+        rtc.write(0x0C, DAY_HIGH_BIT);
+        rtc.advance(86400 + 61);
+        assert_eq!(rtc.registers, [2, 1, 0, 0, DAY_CARRY]);
+
+        // out of range seconds wrap at 63 without carrying
+        let mut rtc = Rtc::default();
+        rtc.write(0x08, 62);
+        rtc.advance(3);
+        assert_eq!(rtc.registers, [1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_footer_round_trip() {
+        let rtc = Rtc {
+            registers: [10, 20, 5, 0x34, DAY_HIGH_BIT | HALT],
+            latched: [1, 2, 3, 4, 0],
+            ..Rtc::default()
+        };
+        let footer = rtc.footer();
+        assert_eq!(footer.len(), FOOTER_SIZE);
+
+        let mut restored = Rtc::default();
+        restored.load_footer(&footer).unwrap();
+        assert_eq!(restored.registers, rtc.registers);
+        assert_eq!(restored.latched, rtc.latched);
+
+        // an hour and a bit since an older short footer was written
+        let mut short = footer[..40].to_vec();
+        short.extend_from_slice(&((unix_time() - 3601) as u32).to_le_bytes());
+        short[16] = DAY_HIGH_BIT;
+        restored.load_footer(&short).unwrap();
+        assert_eq!(restored.registers[MINUTES..=HOURS], [20, 6]);
+        assert!(restored.load_footer(&footer[..20]).is_err());
+    }
+}
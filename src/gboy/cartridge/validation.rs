@@ -0,0 +1,128 @@
+use super::*;
+
+const LOGO_START: usize = 0x104;
+
+/// checks the boot rom (or the hardware) would run on the header, plus the
+/// ones nothing checks but help spotting bad dumps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Validation {
+    // 0x104..=0x133 matches the boot rom copy, a DMG locks up otherwise
+    pub logo: bool,
+    // 0x14D matches 0x134..=0x14C, a DMG locks up otherwise
+    pub header_checksum: bool,
+    // 0x14E..=0x14F matches the sum of every other rom byte
+    pub global_checksum: bool,
+    // rom length matches the size declared on 0x148
+    pub rom_size: bool,
+}
+
+impl Validation {
+    pub fn is_valid(&self) -> bool {
+        self.warnings().is_empty()
+    }
+
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if !self.logo {
+            warnings.push(String::from("nintendo logo mismatch"));
+        }
+        if !self.header_checksum {
+            warnings.push(String::from("header checksum mismatch"));
+        }
+        if !self.global_checksum {
+            warnings.push(String::from("global checksum mismatch"));
+        }
+        if !self.rom_size {
+            warnings.push(String::from("rom length doesn't match declared rom size"));
+        }
+        warnings
+    }
+}
+
+impl Cartridge {
+    pub fn logo_valid(&mut self) -> bool {
+        self.mapper.rom()[LOGO_START..LOGO_START + NINTENDO_LOGO.len()] == NINTENDO_LOGO[..]
+    }
+
+    pub fn header_checksum_valid(&mut self) -> bool {
+        self.header_checksum() == self.calculate_header_checksum()
+    }
+
+    pub fn calculate_global_checksum(&mut self) -> u16 {
+        self.mapper.rom()
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != 0x14E && *i != 0x14F)
+            .fold(0u16, |x, (_, b)| x.wrapping_add(*b as u16))
+    }
+
+    pub fn global_checksum_valid(&mut self) -> bool {
+        self.header.global_checksum == self.calculate_global_checksum()
+    }
+
+    pub fn rom_size_valid(&mut self) -> bool {
+        self.rom_len() == self.header.rom_size
+    }
+
+    pub fn validate(&mut self) -> Validation {
+        Validation {
+            logo: self.logo_valid(),
+            header_checksum: self.header_checksum_valid(),
+            global_checksum: self.global_checksum_valid(),
+            rom_size: self.rom_size_valid(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 32KiB rom with a good header and checksums
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[LOGO_START..LOGO_START + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x134..0x138].copy_from_slice(b"GBOY");
+        rom[0x14D] = rom[0x134..=0x14C].iter().fold(0u8, |x, b| x.wrapping_sub(*b).wrapping_sub(1));
+        let global = rom.iter().fold(0u16, |x, b| x.wrapping_add(*b as u16));
+        rom[0x14E] = (global >> 8) as u8;
+        rom[0x14F] = global as u8;
+        rom
+    }
+
+    fn validate(rom: Vec<u8>) -> Validation {
+        new(rom).unwrap().validate()
+    }
+
+    #[test]
+    fn test_good_rom_is_valid() {
+        let validation = validate(rom());
+        assert!(validation.is_valid(), "{:?}", validation.warnings());
+    }
+
+    #[test]
+    fn test_each_check_warns() {
+        let mut bad_logo = rom();
+        bad_logo[LOGO_START] ^= 0xFF;
+        let validation = validate(bad_logo);
+        assert!(!validation.logo);
+        // the logo counts towards the global checksum too
+        assert_eq!(validation.warnings(), ["nintendo logo mismatch", "global checksum mismatch"]);
+
+        let mut bad_header = rom();
+        bad_header[0x14D] ^= 0xFF;
+        let validation = validate(bad_header);
+        assert!(!validation.header_checksum);
+        assert!(validation.logo);
+
+        let mut bad_global = rom();
+        bad_global[0x14F] ^= 0xFF;
+        assert_eq!(validate(bad_global).warnings(), ["global checksum mismatch"]);
+
+        let mut short = rom();
+        short.truncate(0x4000);
+        let validation = validate(short);
+        assert!(!validation.rom_size);
+        assert!(validation.logo && validation.header_checksum);
+    }
+}
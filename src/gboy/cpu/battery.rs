@@ -0,0 +1,140 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::*;
+
+/// cartridge ram gets flushed about every 5 seconds of emulated time, when changed
+const SAVE_PERIOD_CYCLES: usize = 5 * 4_194_304;
+
+/// writes to a temporary sibling then renames it over the save, a crash
+/// half way leaves the previous save untouched
+pub(super) fn write_atomic(path: &Path, data: &[u8]) -> Result<(), io::Error> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+impl Cpu {
+    /// loads a battery save if it exists, and keeps it updated from then on.
+    /// Cartridges without a battery are left alone
+    pub fn attach_battery_save(&mut self, path: &Path) -> Result<(), io::Error> {
+        if !self.memory.has_battery() {
+            debug!("cartridge without battery, not saving to {:?}", path);
+            return Ok(());
+        }
+        if path.exists() {
+            let save = fs::read(path)?;
+            self.memory.load_battery_save(&save)?;
+            info!("battery save loaded from {:?}", path);
+        }
+        self.controls.saved_battery = self.memory.battery_contents();
+        self.controls.battery_path = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// writes the battery save when cartridge ram (or clock) changed since last time
+    pub fn flush_battery_save(&mut self) {
+        let path = match &self.controls.battery_path {
+            Some(p) => p.clone(),
+            None => return,
+        };
+        // the rtc footer timestamp moves on its own, leave it out of the comparison
+        let contents = self.memory.battery_contents();
+        if contents == self.controls.saved_battery {
+            return;
+        }
+        match write_atomic(&path, &self.memory.battery_save()) {
+            Ok(_) => {
+                debug!("battery save written to {:?}", path);
+                self.controls.saved_battery = contents;
+            },
+            Err(e) => error!("Could not write battery save {:?} => {}", path, e),
+        }
+    }
+
+    pub(crate) fn battery_tick(&mut self) {
+        if self.controls.battery_path.is_none() {
+            return;
+        }
+        if self.registers.clock_cycles().saturating_sub(self.controls.battery_control) < SAVE_PERIOD_CYCLES {
+            return;
+        }
+        self.controls.battery_control = self.registers.clock_cycles();
+        self.flush_battery_save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::thread;
+    use std::time::Duration;
+
+    /// MBC3+TIMER+RAM+BATTERY with 8KiB ram
+    fn battery_cpu() -> Cpu {
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x10;
+        rom[0x149] = 0x02;
+        initialize(rom, None).unwrap()
+    }
+
+    #[test]
+    fn test_flush_only_writes_changes() {
+        let mut cpu = battery_cpu();
+        let path = env::temp_dir().join(format!("gboy-battery-{}.sav", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        cpu.attach_battery_save(&path).unwrap();
+        cpu.flush_battery_save();
+        assert!(!path.exists());
+
+        cpu.memory.write(0x0000_usize, 0x0A);
+        cpu.memory.write(0xA000_usize, 0x42);
+        cpu.flush_battery_save();
+        let save = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(save.len(), 0x2000 + super::super::super::cartridge::rtc::FOOTER_SIZE);
+        assert_eq!(save[0], 0x42);
+
+        // a second later only the footer timestamp differs
+        thread::sleep(Duration::from_millis(1100));
+        cpu.flush_battery_save();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_flush_period_restarts_after_restore() {
+        let mut cpu = battery_cpu();
+        let path = env::temp_dir().join(format!("gboy-battery-restore-{}.sav", std::process::id()));
+        let _ = fs::remove_file(&path);
+        cpu.attach_battery_save(&path).unwrap();
+        let snapshot = cpu.save_machine();
+        cpu.registers.add_clock_cycles(2 * SAVE_PERIOD_CYCLES);
+        cpu.battery_tick();
+
+        // the clock goes back, as on a rewind step or state load
+        cpu.load_machine(&snapshot).unwrap();
+        cpu.memory.write(0x0000_usize, 0x0A);
+        cpu.memory.write(0xA000_usize, 0x42);
+        cpu.battery_tick();
+        let early = path.exists();
+        cpu.registers.add_clock_cycles(SAVE_PERIOD_CYCLES);
+        cpu.battery_tick();
+        let flushed = path.exists();
+        let _ = fs::remove_file(&path);
+
+        assert!(!early, "flushed before a save period went by");
+        assert!(flushed, "no flush a save period after the restore");
+    }
+}
@@ -0,0 +1,209 @@
+//! Single instruction conformance against SingleStepTests (sm83) style vectors
+//! found in tests/vectors, one file per optcode
+//!
+//! cargo test conformance -- --nocapture
+
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use serde_json::Value;
+
+use super::*;
+use super::optcode::OptCode;
+
+/// vector files known to fully pass, any failing case on them is a regression
+const KNOWN_PASSING: &[&str] = &[
+    "00", "01", "02", "03", "06", "0e", "11", "12", "13", "16", "1a", "1e", "21", "23",
+    "26", "2e", "31", "3e", "40", "47", "4f", "57", "5f", "67", "6f", "77", "78", "7a",
+    "7b", "7c", "7d", "7f", "a8", "a9", "aa", "ab", "ac", "ad", "af", "e0", "e2", "ea",
+    "f0",
+];
+
+enum Outcome {
+    Passed,
+    Failed(String),
+    Unimplemented,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct State {
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    pc: usize,
+    sp: usize,
+}
+
+fn byte(v: &Value, field: &str) -> u8 {
+    v[field].as_u64().unwrap_or_else(|| panic!("missing {}", field)) as u8
+}
+
+fn word(v: &Value, field: &str) -> usize {
+    v[field].as_u64().unwrap_or_else(|| panic!("missing {}", field)) as usize
+}
+
+fn parse_state(v: &Value) -> State {
+    State {
+        a: byte(v, "a"),
+        b: byte(v, "b"),
+        c: byte(v, "c"),
+        d: byte(v, "d"),
+        e: byte(v, "e"),
+        f: byte(v, "f"),
+        h: byte(v, "h"),
+        l: byte(v, "l"),
+        pc: word(v, "pc"),
+        sp: word(v, "sp"),
+    }
+}
+
+fn parse_ram(v: &Value) -> Vec<(usize, u8)> {
+    v["ram"]
+        .as_array()
+        .map(|r| r.iter().map(|e| (e[0].as_u64().unwrap() as usize, e[1].as_u64().unwrap() as u8)).collect())
+        .unwrap_or_default()
+}
+
+impl Cpu {
+    fn load_vector(&mut self, s: &State) {
+        self.registers.a = s.a;
+        self.registers.b = s.b;
+        self.registers.c = s.c;
+        self.registers.d = s.d;
+        self.registers.e = s.e;
+        self.registers.f.set_value(s.f);
+        self.registers.h = s.h;
+        self.registers.l = s.l;
+        self.registers.pc.jump(s.pc);
+        self.registers.sp = s.sp;
+    }
+
+    fn state(&mut self) -> State {
+        State {
+            a: self.registers.a,
+            b: self.registers.b,
+            c: self.registers.c,
+            d: self.registers.d,
+            e: self.registers.e,
+            f: self.registers.f.value(),
+            h: self.registers.h,
+            l: self.registers.l,
+            pc: self.registers.pc.value(),
+            sp: self.registers.sp,
+        }
+    }
+}
+
+/// non idle cycles of the vector, (address, value, written)
+fn parse_cycles(v: &Value) -> Vec<(usize, u8, bool)> {
+    v.as_array()
+        .map(|c| {
+            c.iter()
+                .filter(|e| !e[0].is_null())
+                .map(|e| (e[0].as_u64().unwrap() as usize, e[1].as_u64().unwrap() as u8, e[2].as_str().unwrap_or("").contains('w')))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn run_case(case: &Value) -> Outcome {
+    let mut cpu = initialize(vec![0; 0x8000], None).unwrap();
+    cpu.memory.set_bus(Bus::Flat);
+    cpu.load_vector(&parse_state(&case["initial"]));
+    for (address, value) in parse_ram(&case["initial"]) {
+        cpu.memory.write(address, value);
+    }
+    cpu.memory.take_accesses();
+
+    let inst = cpu.read_instruction();
+    let optcode = cpu.decode(inst);
+    match optcode {
+        OptCode::None(_) | OptCode::CBNone(_) => return Outcome::Unimplemented,
+        _ => {},
+    }
+    let clock_cycles = cpu.registers.clock_cycles();
+    cpu.execute(&optcode);
+    let accesses = cpu.memory.take_accesses();
+    cpu.timer_tick(&optcode);
+
+    let expected = parse_state(&case["final"]);
+    let actual = cpu.state();
+    if expected != actual {
+        return Outcome::Failed(format!("{:?}\n  expected {:?}\n  actual   {:?}", optcode, expected, actual));
+    }
+    for (address, value) in parse_ram(&case["final"]) {
+        if cpu.memory[address] != value {
+            return Outcome::Failed(format!("{:?} ram {:#06x} expected {:#x} actual {:#x}", optcode, address, value, cpu.memory[address]));
+        }
+    }
+    let cycles = case["cycles"].as_array().map_or(0, |c| c.len()) * 4;
+    let spent = cpu.registers.clock_cycles() - clock_cycles;
+    if cycles != spent {
+        return Outcome::Failed(format!("{:?} clock cycles expected {} actual {}", optcode, cycles, spent));
+    }
+    let expected = parse_cycles(&case["cycles"]);
+    if expected != accesses {
+        return Outcome::Failed(format!("{:?} bus accesses\n  expected {:x?}\n  actual   {:x?}", optcode, expected, accesses));
+    }
+    Outcome::Passed
+}
+
+#[test]
+fn test_conformance_vectors() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("vectors");
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .collect();
+    files.sort();
+
+    // unknown optcodes and register misuse panic on purpose, the harness
+    // captures their messages so no hook is swapped
+    let mut regressions = Vec::new();
+    println!("{:<8} {:>6} RESULT", "OPTCODE", "CASES");
+    for file in &files {
+        let name = file.file_stem().unwrap().to_string_lossy().to_string();
+        let cases: Value = serde_json::from_str(&fs::read_to_string(file).unwrap()).unwrap();
+        let cases = cases.as_array().unwrap();
+
+        let mut passed = 0;
+        let mut first_failure = None;
+        let mut unimplemented = false;
+        for case in cases {
+            match panic::catch_unwind(AssertUnwindSafe(|| run_case(case))) {
+                Ok(Outcome::Passed) => passed += 1,
+                Ok(Outcome::Unimplemented) => {
+                    unimplemented = true;
+                    break;
+                },
+                Ok(Outcome::Failed(why)) => {
+                    first_failure.get_or_insert(format!("{}: {}", case["name"], why));
+                },
+                Err(_) => {
+                    first_failure.get_or_insert(format!("{}: panicked", case["name"]));
+                },
+            }
+        }
+
+        if unimplemented {
+            println!("{:<8} {:>6} ---- (not implemented)", name, cases.len());
+        } else if passed == cases.len() {
+            println!("{:<8} {:>6} PASS", name, cases.len());
+        } else {
+            println!("{:<8} {:>6} FAIL {}/{} {}", name, cases.len(), passed, cases.len(), first_failure.unwrap_or_default());
+        }
+
+        if passed != cases.len() && KNOWN_PASSING.contains(&name.as_str()) {
+            regressions.push(name);
+        }
+    }
+
+    assert!(regressions.is_empty(), "optcodes not passing anymore => {:?}", regressions);
+}
@@ -6,7 +6,7 @@ use crate::gboy::cpu::optcode::OptCode;
 
 #[derive(Debug)]
 pub enum CpuDebuggerMessage {
-    CatridgeTitle(String),
+    Cartridge(Header),
     OptCode(OptCode),
     Registers {
         a: u8,
@@ -49,7 +49,7 @@ pub trait CpuDebugger {
 impl Cpu {
     pub(crate) fn initialize_debugger(&mut self) {
         if let Some(d) = &mut self.debugger {
-            d.message(CpuDebuggerMessage::CatridgeTitle(self.memory.cartridge_title()));
+            d.message(CpuDebuggerMessage::Cartridge(self.memory.cartridge_header().clone()));
             d.initialize();
         }
     }
@@ -0,0 +1,79 @@
+use std::str::FromStr;
+
+use super::*;
+use super::super::memorybus::joypad;
+
+/// buttons held from a frame on, counted from when the script was given.
+/// Written frame:buttons, buttons being names joined by +, `120:a+start`,
+/// and nothing after the colon releasing them all
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScriptedInput {
+    pub frame: usize,
+    pub buttons: u8,
+}
+
+fn button(name: &str) -> Option<u8> {
+    match name {
+        "right" => Some(joypad::RIGHT),
+        "left" => Some(joypad::LEFT),
+        "up" => Some(joypad::UP),
+        "down" => Some(joypad::DOWN),
+        "a" => Some(joypad::A),
+        "b" => Some(joypad::B),
+        "select" => Some(joypad::SELECT),
+        "start" => Some(joypad::START),
+        _ => None,
+    }
+}
+
+impl FromStr for ScriptedInput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ScriptedInput, String> {
+        let (frame, names) = s.split_once(':').ok_or_else(|| format!("input {:?} is not frame:buttons", s))?;
+        let frame = frame.trim().parse().map_err(|_| format!("bad input frame {:?}", frame))?;
+        let mut buttons = 0;
+        for name in names.split('+').map(|n| n.trim().to_lowercase()).filter(|n| !n.is_empty()) {
+            buttons |= button(&name).ok_or_else(|| {
+                format!("unknown button {:?}, expected right, left, up, down, a, b, select or start", name)
+            })?;
+        }
+        Ok(ScriptedInput { frame, buttons })
+    }
+}
+
+impl Cpu {
+    /// presses buttons on the given frames, as a frontend would, so they are
+    /// recorded on movies too
+    pub fn script_input(&mut self, mut script: Vec<ScriptedInput>) {
+        script.sort_by_key(|i| i.frame);
+        self.controls.input_script = script.into();
+        self.controls.input_frame = self.memory.frames();
+    }
+
+    pub(crate) fn input_tick(&mut self) {
+        let frame = self.memory.frames() - self.controls.input_frame;
+        while let Some(input) = self.controls.input_script.front().copied() {
+            if input.frame > frame {
+                break;
+            }
+            self.controls.input_script.pop_front();
+            self.set_buttons(input.buttons);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripted_input_from_str() {
+        assert_eq!("120:A+start".parse(), Ok(ScriptedInput { frame: 120, buttons: joypad::A | joypad::START }));
+        assert_eq!("3: right + down".parse(), Ok(ScriptedInput { frame: 3, buttons: joypad::RIGHT | joypad::DOWN }));
+        assert_eq!("7:".parse(), Ok(ScriptedInput { frame: 7, buttons: 0 }));
+        assert!("start".parse::<ScriptedInput>().is_err());
+        assert!("x:a".parse::<ScriptedInput>().is_err());
+        assert!("1:a+turbo".parse::<ScriptedInput>().is_err());
+    }
+}
@@ -7,6 +7,7 @@ impl Cpu {
 
         match *optcode {
             OptCode::NOP => self.nop(),
+            OptCode::STOP => self.stop(),
             OptCode::LDnn(rt, a, b) => self.ld_nn(rt, a, b),
             OptCode::XORn(rt) => self.xor_n(rt),
             OptCode::LDDHLA => self.ldd_hl_a(),
@@ -58,6 +59,15 @@ impl Cpu {
     fn nop(&mut self) {
         debug!("nop");
     }
+
+    fn stop(&mut self) {
+        debug!("stop");
+        // on CGB, with KEY1 armed, it's how the cpu speed gets switched
+        if !self.memory.switch_speed() {
+            // low power mode until a button is pressed, not emulated yet
+            debug!("stop mode not emulated, carrying on");
+        }
+    }
     
     fn ld_nn(&mut self, rt: RegisterType, a: u8, b: u8) {
         let address = util::join_bytes(a, b);
@@ -96,7 +106,7 @@ impl Cpu {
         let hl = self.registers.hl();
         debug!("ldd_hl_a => hl:{:#x} a:{:#x}", hl, self.registers.a);
         // self.memory.write(hl as usize, self.registers.a);
-        self.memory[hl] = self.registers.a;
+        self.memory.write(hl, self.registers.a);
         self.registers.dec_hl();
     }
 
@@ -127,7 +137,7 @@ impl Cpu {
         let address = util::join_bytes(a, b);
         debug!("ld_nn_a a:{:#x} b:{:#x}, address:{:#x}", a, b, address);
         //self.memory.write(address as usize, self.registers.a);
-        self.memory[address] = self.registers.a;
+        self.memory.write(address, self.registers.a);
         //self.controls.quit = true;
     }
 
@@ -146,7 +156,7 @@ impl Cpu {
         let address = self.registers.get2(&to);
         trace!("{:?}:{:#x} => ({:?}):{:#x}", from, value, to, address);
         // self.memory.write(address as usize, value);
-        self.memory[address] = value;
+        self.memory.write(address, value);
         self.registers.dec2(&to);
 
         //self.controls.quit = true;
@@ -157,7 +167,7 @@ impl Cpu {
         let address = FF00 + self.registers.c as usize;
         error!("ld_c_a c:{:#x} a:{:#x} address:{:#x}", self.registers.c, self.registers.a, address);
         // self.memory.write_ff00(self.registers.c as usize, self.registers.a)
-        self.memory[address] = self.registers.a;
+        self.memory.write(address, self.registers.a);
         //self.controls.quit = true;
     }
 
@@ -209,7 +219,7 @@ impl Cpu {
         let address = self.registers.get2(&rt);
         debug!("address:{:#x}", address);
         // self.memory.write(address as usize, self.registers.a);
-        self.memory[address] = self.registers.a;
+        self.memory.write(address, self.registers.a);
 
         //self.controls.quit = true;
     }
@@ -217,7 +227,7 @@ impl Cpu {
     fn ldh_n_a(&mut self, value: u8) {
         error!("ldh_n_a value:0xFF00+{:#x} = a:{:#x}", value, self.registers.a);
         // self.memory.write_ff00(value as usize, self.registers.a);
-        self.memory[FF00 + value as usize] = self.registers.a;
+        self.memory.write(FF00 + value as usize, self.registers.a);
 
         //self.controls.quit = true;
     }
@@ -375,19 +385,19 @@ impl Cpu {
 
     fn di(&mut self) {
         debug!("DI");
-        self.memory[0xFFFF as usize] = 0x0;
+        self.memory.write(0xFFFF as usize, 0x0);
     }
 
     fn ei(&mut self) {
         debug!("EI");
-        self.memory[0xFFFF as usize] = 0b0001_1111;
+        self.memory.write(0xFFFF as usize, 0b0001_1111);
     }
     
     /// Internals
     fn stack_push(&mut self, value: u8) {
         trace!("stack_push sp:{:#} value:{:#x}", self.registers.sp, value);
         // self.memory.write(self.registers.sp, value);
-        self.memory[self.registers.sp] = value;
+        self.memory.write(self.registers.sp, value);
         self.registers.sp -= 1;
     }
     
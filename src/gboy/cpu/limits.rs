@@ -0,0 +1,72 @@
+use super::*;
+use super::optcode::OptCode;
+
+/// conditions that stop the emulation, any of them reached quits
+#[derive(Debug, Default, Clone)]
+pub struct RunLimits {
+    pub frames: Option<usize>,
+    pub cycles: Option<usize>,
+    pub until_pc: Option<usize>,
+    pub until_serial: Vec<String>,
+    /// LD B,B software breakpoint, as used by mooneye and mealybug test roms
+    pub until_breakpoint: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExitReason {
+    /// emulation gave up by itself, unknown optcode, bootrom lock down...
+    Quit,
+    Frames,
+    Cycles,
+    Pc,
+    Serial,
+    Breakpoint,
+    /// movie playback ran out of input
+    MovieEnd,
+    /// movie playback hash mismatch, when verifying
+    Desync,
+}
+
+impl Cpu {
+    pub fn set_limits(&mut self, limits: RunLimits) {
+        self.controls.limits = limits;
+    }
+
+    pub fn exit_reason(&self) -> ExitReason {
+        self.controls.exit.unwrap_or(ExitReason::Quit)
+    }
+
+    /// every byte sent through the serial port so far
+    pub fn serial_output(&self) -> &[u8] {
+        &self.controls.serial_output
+    }
+
+    pub(crate) fn limits_tick(&mut self, optcode: &OptCode) {
+        let pc = self.registers.pc.value();
+        let clock_cycles = self.registers.clock_cycles();
+        let limits = &self.controls.limits;
+        let breakpoint = matches!(*optcode, OptCode::LDNn(RegisterType::B, RegisterType::B));
+        let reason = if limits.until_pc == Some(pc) {
+            Some(ExitReason::Pc)
+        } else if limits.until_breakpoint && breakpoint {
+            Some(ExitReason::Breakpoint)
+        } else if !limits.until_serial.is_empty() && {
+            let serial = String::from_utf8_lossy(&self.controls.serial_output);
+            limits.until_serial.iter().any(|s| serial.contains(s.as_str()))
+        } {
+            Some(ExitReason::Serial)
+        } else if limits.frames.is_some_and(|f| self.memory.frames() >= f) {
+            Some(ExitReason::Frames)
+        } else if limits.cycles.is_some_and(|c| clock_cycles >= c) {
+            Some(ExitReason::Cycles)
+        } else {
+            None
+        };
+
+        if let Some(r) = reason {
+            info!("quitting due limit reached => {:?}", r);
+            self.controls.exit = Some(r);
+            self.controls.quit = true;
+        }
+    }
+}
@@ -5,14 +5,33 @@ mod registers;
 mod instructions;
 mod timer;
 mod interrupt;
+mod serial;
+mod limits;
+mod battery;
+mod state;
+mod rewind;
+mod movie;
+mod timing;
+mod input;
+#[cfg(test)]
+mod conformance;
 
 pub use debugger::*;
+pub use serial::*;
+pub use limits::*;
+pub use input::*;
 use super::cartridge::*;
 use super::memorybus::*;
+use super::model::Model;
+use super::screen::*;
 use registers::*;
 use timer::*;
 
 use log::Level;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
 const ROM_INITIAL_ADDRESS: usize = 0x100;
 const SP_INITIAL_ADDRESS: usize = 0xFFFE;
@@ -24,6 +43,23 @@ struct Control {
     game_booted: bool,
     quit: bool,
     div_control: usize,
+    serial_control: usize,
+    serial_output: Vec<u8>,
+    limits: RunLimits,
+    exit: Option<ExitReason>,
+    cpu_clock: usize,
+    device_clock: usize,
+    battery_path: Option<PathBuf>,
+    battery_control: usize,
+    saved_battery: Vec<u8>,
+    rom_sha1: Option<String>,
+    rewind: Option<super::rewind::Rewind>,
+    rewind_frame: usize,
+    movie: Option<movie::MovieRun>,
+    pacer: Option<super::timing::Pacer>,
+    timing_frame: Option<usize>,
+    input_script: std::collections::VecDeque<ScriptedInput>,
+    input_frame: usize,
 }
 
 pub struct Cpu {
@@ -31,18 +67,22 @@ pub struct Cpu {
     registers: Registers,
     controls: Control,
     debugger: Option<Box<dyn CpuDebugger>>,
+    serial: Option<Box<dyn SerialDevice>>,
 }
 
-pub fn initialize(gamerom: Vec<u8>, mut debugger: Option<Box<dyn CpuDebugger>>) -> Cpu {
+pub fn initialize(gamerom: Vec<u8>, debugger: Option<Box<dyn CpuDebugger>>) -> Result<Cpu, io::Error> {
+    Ok(with_cartridge(super::cartridge::new(gamerom)?, debugger))
+}
 
-    let mut cartridge = super::cartridge::new(gamerom);
-    let mut memory = super::memorybus::new(cartridge);
+pub(crate) fn with_cartridge(cartridge: Cartridge, debugger: Option<Box<dyn CpuDebugger>>) -> Cpu {
+    let memory = super::memorybus::new(cartridge);
     
     Cpu {
         memory: memory,
         registers: Registers::default(),
         controls: Control::default(),
         debugger: debugger,
+        serial: None,
     }
 }
 
@@ -53,19 +93,14 @@ impl Cpu {
     fn load_bootrom(&mut self, bootrom: Option<Vec<u8>>) {
         match bootrom {
             Some(b) => {
-                for i in 0..b.len() {
-                    // self.memory.write(0x0 + i, b[i]);
-                    //info!("{:#x} => {:#x}", i, b[i]);
-                    self.memory[0x0 + i] = b[i];
-                }
+                self.memory.load_bootrom(b);
             },
 
             None => {
-                // disables bootrom
-                self.registers.a = 0x01;
+                // registers as the model bootrom leaves them
+                self.load_boot_registers();
                 // unmaps bootrom
-                // self.memory.write_ff00(0x50, self.registers.a);
-                self.memory[FF00 + 0x50] = self.registers.a;
+                self.memory.write(FF00 + 0x50, 0x01);
                 // setting PC to initial ROM address, usyally let on 0x100 by bootrom
                 self.registers.pc.jump(ROM_INITIAL_ADDRESS);
                 // clearing up stack pointer
@@ -76,19 +111,40 @@ impl Cpu {
         }
     }
     
-    fn load_gamerom(&mut self) {
-        trace!("loading gamerom");
-        self.memory.load_cartridge_rom();
-        trace!("gamerom fully loaded to ram");
-        //panic!("");
+    fn load_boot_registers(&mut self) {
+        let (a, f, b, c, d, e, h, l) = match self.memory.model() {
+            Model::Dmg => (0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D),
+            Model::Sgb => (0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60),
+            Model::Cgb => (0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D),
+            Model::CgbCompat => (0x11, 0x80, 0x00, 0x00, 0x00, 0x08, 0x00, 0x7C),
+        };
+        self.registers.a = a;
+        self.registers.f.set_value(f);
+        self.registers.b = b;
+        self.registers.c = c;
+        self.registers.d = d;
+        self.registers.e = e;
+        self.registers.h = h;
+        self.registers.l = l;
     }
-    
+
+    pub fn cartridge_header(&self) -> &Header {
+        self.memory.cartridge_header()
+    }
+
+    /// hardware to emulate, must be set before bootup
+    pub fn set_model(&mut self, model: Model) {
+        info!("emulating {}", model);
+        self.memory.set_model(model);
+    }
+
+    pub fn validate_cartridge(&mut self) -> Validation {
+        self.memory.validate_cartridge()
+    }
+
     pub fn bootup(&mut self, bootrom: Option<Vec<u8>>) {
-        self.memory.load_cartridge_header();
         self.load_bootrom(bootrom);
         
-        // self.memory.write_ff00(0x44, 0x90); //enabling VBlank
-        self.memory[FF00 + 0x44] = 0x90; //enabling VBlank
         //self.registers.ime = true; // Enabling Interrupt Master Enable Flag
 
         self.initialize_debugger();
@@ -131,6 +187,8 @@ impl Cpu {
             self.message_debugger(CpuDebuggerMessage::OptCode(optcode.clone()));
             self.execute(&optcode);
             self.timer_tick(&optcode);
+            self.device_tick();
+            self.limits_tick(&optcode);
                 
             if self.controls.quit || self.controls.game_booted {
                 info!("quitting bootrom loop");
@@ -140,9 +198,13 @@ impl Cpu {
     }
     
     fn run_game(&mut self) {
+        self.reset_serial();
         loop {
             trace!("looping gamerom");
             self.tick_debugger();
+            if self.rewind_held() {
+                continue;
+            }
             
             // checking if coming from a jump or a walk
             if self.controls.old_pc == self.registers.pc.value() {
@@ -159,11 +221,14 @@ impl Cpu {
             self.message_debugger(CpuDebuggerMessage::OptCode(optcode.clone()));
             self.execute(&optcode);
             self.timer_tick(&optcode);
-
-            if self.registers.pc.value() == 0x237 {
-                info!("quitting due PC value");
-                self.controls.quit = true;
-            }
+            self.device_tick();
+            self.serial_tick();
+            self.battery_tick();
+            self.limits_tick(&optcode);
+            self.rewind_tick();
+            self.input_tick();
+            self.movie_tick();
+            self.timing_tick();
              
             if self.controls.quit {
                 info!("game looping quitting");
@@ -174,7 +239,24 @@ impl Cpu {
         }
     }
 
-    pub fn run(&mut self) {
+    /// walks gpu and cartridge, they keep their clock on CGB double speed
+    /// mode while the cpu (and timers) run twice as fast
+    fn device_tick(&mut self) {
+        let elapsed = self.registers.clock_cycles() - self.controls.cpu_clock;
+        self.controls.cpu_clock = self.registers.clock_cycles();
+        self.controls.device_clock += if self.memory.double_speed() { elapsed / 2 } else { elapsed };
+        self.memory.gpu_tick(self.controls.device_clock);
+        self.memory.cartridge_tick(self.controls.device_clock);
+
+        // the cpu sits idle while vram DMA copies, devices keep going
+        let stall = self.memory.take_dma_stall();
+        if stall > 0 {
+            self.registers.add_clock_cycles(stall);
+            self.device_tick();
+        }
+    }
+
+    pub fn run(&mut self) -> ExitReason {
         if !self.controls.game_booted {
             self.run_bootrom();
         } else {
@@ -182,15 +264,17 @@ impl Cpu {
         }
 
         if self.controls.game_booted && !self.controls.quit {
-            self.load_gamerom();
             self.run_game();
         } else {
             error!("skipping gamerom, probably something went wrong on bootrom process");
         }
+        self.flush_battery_save();
         
         if log_enabled!(log::Level::Debug) {
             self.dump();
         }        
+
+        self.exit_reason()
     }
     
     fn read_instruction(&mut self) -> u8 {
@@ -202,26 +286,28 @@ impl Cpu {
 
     fn dump(&mut self) {
         println!("======     CARTRIDGE     =======");
-        println!("Cartridge Title: {}", self.memory.cartridge_title());
-        println!("Destination Code: {}", self.memory.destination_code());
-        println!("Licensee Code: {}", self.memory.licensee_code());
-        println!("Type: {}", self.memory.cartridge_type());
+        let header = self.memory.cartridge_header().clone();
+        println!("Model: {}", self.memory.model());
+        println!("Cartridge Title: {}", header.title);
+        if let Some(m) = &header.manufacturer {
+            println!("Manufacturer Code: {}", m);
+        }
+        println!("CGB Flag: {:?}", header.cgb);
+        println!("SGB Flag: {}", header.sgb);
+        println!("Licensee: {}", header.licensee());
+        println!("Type: {} ({:#04x})", header.cartridge_type_name(), header.cartridge_type);
+        println!("ROM Size: {}KiB", header.rom_size / 1024);
+        println!("RAM Size: {}KiB", header.ram_size / 1024);
+        println!("Destination Code: {}", header.destination_name());
+        println!("Version: {}", header.version);
         let (c, s) = self.memory.cartridge_header_checksum();
         println!("Header Checksum: {:#x} calculated: {:#x}", c, s);
+        println!("Global Checksum: {:#06x}", header.global_checksum);
+        for w in self.memory.validate_cartridge().warnings() {
+            println!("Warning: {}", w);
+        }
 
-        println!("======     REGISTERS     =======");
-        println!("A == {:#x} ", self.registers.a);
-        println!("B == {:#x} ", self.registers.b);
-        println!("C == {:#x} ", self.registers.c);
-        println!("D == {:#x} ", self.registers.d);
-        println!("E == {:#x} ", self.registers.e);
-        println!("F => Z:{:#x} S:{:#x} H:{:#x} C:{:#x} ", self.registers.f.zero_flag(), self.registers.f.subtract_flag(), self.registers.f.half_carry_flag(), self.registers.f.carry_flag(), );
-        println!("G == {:#x} ", self.registers.g);
-        println!("H == {:#x} ", self.registers.h);
-        println!("L == {:#x} ", self.registers.l);
-        println!("PC == {:#x} ", self.registers.pc.value());
-        println!("SP == {:#x} ", self.registers.sp);
-        //println!("IME == {} ", self.registers.ime);
+        self.dump_registers();
 
         println!("======     STACK     =======");
         for i in self.registers.sp..self.memory.memory_size() {
@@ -242,4 +328,129 @@ impl Cpu {
         println!("STAT FF41 == 0b{:08b}", self.memory[FF00 + 0x41]);
         
     }
+
+    pub fn dump_registers(&mut self) {
+        println!("======     REGISTERS     =======");
+        println!("A == {:#x} ", self.registers.a);
+        println!("B == {:#x} ", self.registers.b);
+        println!("C == {:#x} ", self.registers.c);
+        println!("D == {:#x} ", self.registers.d);
+        println!("E == {:#x} ", self.registers.e);
+        println!("F => Z:{:#x} S:{:#x} H:{:#x} C:{:#x} ", self.registers.f.zero_flag(), self.registers.f.subtract_flag(), self.registers.f.half_carry_flag(), self.registers.f.carry_flag(), );
+        println!("G == {:#x} ", self.registers.g);
+        println!("H == {:#x} ", self.registers.h);
+        println!("L == {:#x} ", self.registers.l);
+        println!("PC == {:#x} ", self.registers.pc.value());
+        println!("SP == {:#x} ", self.registers.sp);
+        //println!("IME == {} ", self.registers.ime);
+    }
+
+    /// writes the whole address space, as seen by the cpu, to path
+    pub fn dump_memory(&mut self, path: &Path) -> Result<(), io::Error> {
+        let mut data = Vec::with_capacity(self.memory.memory_size() + 1);
+        for i in 0..=self.memory.memory_size() {
+            data.push(self.memory[i]);
+        }
+        File::create(path)?.write_all(&data)
+    }
+
+    /// shades framebuffer, SCREEN_WIDTH pixels per line
+    pub fn framebuffer(&self) -> &[u8] {
+        self.memory.framebuffer()
+    }
+
+    /// RGB555 framebuffer, None unless emulating a CGB
+    pub fn color_framebuffer(&self) -> Option<&[u16]> {
+        self.memory.color_framebuffer()
+    }
+
+    /// picture as RGB888 with its width and height, colors on CGB, the
+    /// border and palettes on SGB and DMG shades otherwise
+    pub fn screen(&self, post: &PostProcess) -> (usize, usize, Vec<u8>) {
+        if self.memory.model() == Model::Sgb {
+            return (SGB_WIDTH, SGB_HEIGHT, rgb555_to_rgb(&self.memory.sgb_frame()));
+        }
+        let rgb = match self.color_framebuffer() {
+            Some(colors) => post.colors(colors),
+            None => post.shades(self.framebuffer()),
+        };
+        (SCREEN_WIDTH, SCREEN_HEIGHT, rgb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::optcode::OptCode;
+
+    const LCDC: usize = 0xFF40;
+    const LY: usize = 0xFF44;
+    const KEY1: usize = 0xFF4D;
+    const LINE_CYCLES: usize = 456;
+
+    fn run_cycles(cpu: &mut Cpu, cycles: usize) {
+        for _ in 0..cycles / 4 {
+            cpu.registers.add_clock_cycles(4);
+            cpu.device_tick();
+        }
+    }
+
+    #[test]
+    fn test_stop_switches_speed_while_ppu_keeps_its_clock() {
+        let mut cpu = initialize(vec![0; 0x8000], None).unwrap();
+        cpu.set_model(Model::Cgb);
+        cpu.memory.write(LCDC, 0x91);
+        run_cycles(&mut cpu, LINE_CYCLES);
+        assert_eq!(cpu.memory[LY], 1);
+
+        // STOP without KEY1 prepared leaves the speed alone
+        cpu.execute(&OptCode::STOP);
+        assert!(!cpu.memory.double_speed());
+        cpu.memory.write(KEY1, 0x01);
+        cpu.execute(&OptCode::STOP);
+        assert!(cpu.memory.double_speed());
+        assert_eq!(cpu.memory[KEY1], 0xFE);
+
+        // a line now takes twice the cpu cycles
+        run_cycles(&mut cpu, LINE_CYCLES);
+        assert_eq!(cpu.memory[LY], 1);
+        run_cycles(&mut cpu, LINE_CYCLES);
+        assert_eq!(cpu.memory[LY], 2);
+        assert_eq!(cpu.controls.device_clock, 2 * LINE_CYCLES);
+
+        cpu.memory.write(KEY1, 0x01);
+        cpu.execute(&OptCode::STOP);
+        assert!(!cpu.memory.double_speed());
+        run_cycles(&mut cpu, LINE_CYCLES);
+        assert_eq!(cpu.memory[LY], 3);
+    }
+
+    #[test]
+    fn test_stop_keeps_dmg_speed() {
+        let mut cpu = initialize(vec![0; 0x8000], None).unwrap();
+        cpu.set_model(Model::Dmg);
+        cpu.memory.write(KEY1, 0x01);
+        cpu.execute(&OptCode::STOP);
+        assert!(!cpu.memory.double_speed());
+        assert_eq!(cpu.memory[KEY1], 0xFF);
+    }
+
+    #[test]
+    fn test_boot_registers_per_model() {
+        let expected = [
+            (Model::Dmg, [0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D]),
+            (Model::Sgb, [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60]),
+            (Model::Cgb, [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D]),
+            (Model::CgbCompat, [0x11, 0x80, 0x00, 0x00, 0x00, 0x08, 0x00, 0x7C]),
+        ];
+        for (model, registers) in expected {
+            let mut cpu = initialize(vec![0; 0x8000], None).unwrap();
+            cpu.set_model(model);
+            cpu.bootup(None);
+            let r = &mut cpu.registers;
+            assert_eq!([r.a, r.f.value(), r.b, r.c, r.d, r.e, r.h, r.l], registers, "{}", model);
+            assert_eq!(cpu.registers.pc.value(), ROM_INITIAL_ADDRESS);
+            assert_eq!(cpu.registers.sp, SP_INITIAL_ADDRESS);
+        }
+    }
 }
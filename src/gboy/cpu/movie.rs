@@ -0,0 +1,133 @@
+use std::io;
+
+use super::*;
+use super::super::movie::Movie;
+
+/// movie being recorded or played back on the running machine
+pub(crate) struct MovieRun {
+    movie: Movie,
+    playing: bool,
+    verify: bool,
+    start_frame: usize,
+    last_frame: usize,
+    desync: Option<usize>,
+    // input given while recording, latched on the next frame boundary
+    pending: u8,
+}
+
+impl Cpu {
+    /// starts logging joypad input from now on, embedding the current
+    /// machine as start state unless the game is at power on
+    pub fn record_movie(&mut self, from_state: bool, hash_interval: usize) {
+        let start_state = if from_state { Some(self.save_state()) } else { None };
+        let movie = Movie::new(self.state_header(), start_state, hash_interval);
+        info!("recording movie, hashes every {} frames", hash_interval);
+        self.start_movie(movie, false, false);
+    }
+
+    /// replays a movie, from its start state if it has one. With verify the
+    /// run stops on the first hash mismatch
+    pub fn play_movie(&mut self, movie: Movie, verify: bool) -> Result<(), io::Error> {
+        movie.header.check(&self.state_header())?;
+        if let Some(state) = &movie.start_state {
+            self.load_state(state)?;
+        }
+        info!("playing movie, {} frames", movie.inputs.len());
+        self.start_movie(movie, true, verify);
+        Ok(())
+    }
+
+    fn start_movie(&mut self, movie: Movie, playing: bool, verify: bool) {
+        let frame = self.memory.frames();
+        self.controls.movie = Some(MovieRun {
+            movie,
+            playing,
+            verify,
+            start_frame: frame,
+            last_frame: frame,
+            desync: None,
+            pending: self.memory.buttons(),
+        });
+        self.movie_frame(0);
+    }
+
+    /// joypad input from a frontend, while recording it takes effect on the
+    /// next frame so playback can replay it at the same point, while playing
+    /// back the movie has the joypad
+    pub fn set_buttons(&mut self, buttons: u8) {
+        match &mut self.controls.movie {
+            Some(m) if m.playing => {},
+            Some(m) => m.pending = buttons,
+            None => self.memory.set_buttons(buttons),
+        }
+    }
+
+    /// movie recorded so far, or the one being played
+    pub fn movie(&self) -> Option<&Movie> {
+        self.controls.movie.as_ref().map(|m| &m.movie)
+    }
+
+    /// first frame whose hash didn't match the movie
+    pub fn movie_desync(&self) -> Option<usize> {
+        self.controls.movie.as_ref().and_then(|m| m.desync)
+    }
+
+    pub(crate) fn movie_tick(&mut self) {
+        let frames = self.memory.frames();
+        let frame = match &mut self.controls.movie {
+            Some(m) if m.last_frame != frames => {
+                m.last_frame = frames;
+                frames - m.start_frame
+            },
+            _ => return,
+        };
+        self.movie_frame(frame);
+    }
+
+    /// frame boundary, input for the frame starting is logged or applied
+    fn movie_frame(&mut self, frame: usize) {
+        let hash_due = match &self.controls.movie {
+            Some(m) => m.movie.hash_interval > 0 && frame > 0 && frame.is_multiple_of(m.movie.hash_interval),
+            None => return,
+        };
+        let hash = if hash_due { Some(crc32fast::hash(&self.save_machine())) } else { None };
+
+        let m = match &mut self.controls.movie {
+            Some(m) => m,
+            None => return,
+        };
+        if !m.playing {
+            if let Some(h) = hash {
+                m.movie.hashes.push(h);
+            }
+            m.movie.inputs.push(m.pending);
+            let pending = m.pending;
+            self.memory.set_buttons(pending);
+            return;
+        }
+
+        if let Some(h) = hash {
+            let expected = m.movie.hashes.get(frame / m.movie.hash_interval - 1);
+            if expected.is_some_and(|e| *e != h) && m.desync.is_none() {
+                warn!("movie desync at frame {}", frame);
+                m.desync = Some(frame);
+                if m.verify {
+                    self.controls.exit = Some(ExitReason::Desync);
+                    self.controls.quit = true;
+                    return;
+                }
+            }
+        }
+        match m.movie.inputs.get(frame) {
+            Some(b) => {
+                let b = *b;
+                self.memory.set_buttons(b);
+            },
+            None => {
+                info!("movie ended after {} frames", frame);
+                self.controls.exit = Some(ExitReason::MovieEnd);
+                self.controls.quit = true;
+            },
+        }
+    }
+}
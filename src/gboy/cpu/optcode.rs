@@ -5,6 +5,8 @@ use super::super::*;
 pub enum OptCode {
 
     NOP,
+    #[allow(clippy::upper_case_acronyms)]
+    STOP,
     LDnn(RegisterType, u8, u8),
     XORn(RegisterType),
     LDDHLA,
@@ -61,6 +63,12 @@ impl Cpu {
 
             // Nop
             0x0 => OptCode::NOP,
+
+            // STOP, followed by a padding byte
+            0x10 => {
+                self.read_instruction();
+                OptCode::STOP
+            },
             
             // LD (BC | DE | HL | SP),nn
             // 16bits instructions are stored in Bigendian instead of Littleendian
@@ -130,6 +138,9 @@ impl Cpu {
             },
 
             // LD a,n
+            // LD B,B also used as software breakpoint by test roms
+            0x40 => OptCode::LDNn(RegisterType::B, RegisterType::B),
+
             0x7F | 0x78 | 0x79 | 0x7A | 0x7B | 0x7C | 0x7D | 0x0A | 0x1A | 0x7E | 0xFA | 0x3E => {
                 match optcode {
                     0x7F => OptCode::LDNn(RegisterType::A, RegisterType::A),
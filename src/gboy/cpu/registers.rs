@@ -1,4 +1,7 @@
 
+use std::io;
+
+use super::super::state::{StateReader, StateWriter};
 use super::super::util;
 
 const ZERO_FLAG_POSITION: u8 = 7;
@@ -92,6 +95,11 @@ impl FlagRegister {
         self.value
     }
 
+    /// lower nibble is always zero on hardware
+    pub fn set_value(&mut self, value: u8) {
+        self.value = value & 0xF0;
+    }
+
     pub fn set(&mut self, frt: FlagRegisterType, state: bool) {
         let flag_position = match frt {
             FlagRegisterType::Zero => ZERO_FLAG_POSITION,
@@ -187,6 +195,31 @@ pub struct Registers {
 }
 
 impl Registers {
+    pub fn save_state(&self, w: &mut StateWriter) {
+        let mut registers = *self;
+        for r in [self.a, self.b, self.c, self.d, self.e, registers.f.value(), self.g, self.h, self.l].iter() {
+            w.u8(*r);
+        }
+        w.usize(registers.pc.value());
+        w.usize(self.sp);
+        w.usize(self.clock_cycles);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        self.a = r.u8()?;
+        self.b = r.u8()?;
+        self.c = r.u8()?;
+        self.d = r.u8()?;
+        self.e = r.u8()?;
+        self.f.set_value(r.u8()?);
+        self.g = r.u8()?;
+        self.h = r.u8()?;
+        self.l = r.u8()?;
+        self.pc.jump(r.usize()?);
+        self.sp = r.usize()?;
+        self.clock_cycles = r.usize()?;
+        Ok(())
+    }
     
     pub fn set_hl(&mut self, value: u16) {
         self.set2(&RegisterType::HL, value)
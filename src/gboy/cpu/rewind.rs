@@ -0,0 +1,64 @@
+use super::*;
+use super::super::rewind::{Rewind, RewindConfig};
+
+impl Cpu {
+    /// starts keeping snapshots to step back to
+    pub fn enable_rewind(&mut self, config: RewindConfig) {
+        info!("rewind enabled, {:?}", config);
+        self.controls.rewind = Some(Rewind::new(config));
+        self.controls.rewind_frame = self.memory.frames();
+    }
+
+    /// snapshots the machine every interval frames
+    pub(crate) fn rewind_tick(&mut self) {
+        let frames = self.memory.frames();
+        if frames == self.controls.rewind_frame {
+            return;
+        }
+        self.controls.rewind_frame = frames;
+        let due = match &self.controls.rewind {
+            Some(r) => frames.is_multiple_of(r.interval()),
+            None => false,
+        };
+        if due {
+            let snapshot = self.save_machine();
+            if let Some(r) = &mut self.controls.rewind {
+                r.push(snapshot);
+                trace!("rewind snapshot, {} kept in {} bytes", r.len(), r.memory_used());
+            }
+        }
+    }
+
+    /// goes back one snapshot, the run does it once a frame while the rewind
+    /// hotkey is held. False once there's no history left
+    pub fn rewind_step(&mut self) -> bool {
+        let snapshot = match self.controls.rewind.as_mut().and_then(|r| r.step_back()) {
+            Some(s) => s,
+            None => return false,
+        };
+        if let Err(e) = self.load_machine(&snapshot) {
+            error!("Could not rewind => {}", e);
+            return false;
+        }
+        self.controls.rewind_frame = self.memory.frames();
+        true
+    }
+
+    /// while the rewind hotkey is held the game stands still and steps back
+    /// one snapshot every paced frame, letting go once history runs out
+    pub(crate) fn rewind_held(&mut self) -> bool {
+        let control = match &mut self.controls.pacer {
+            Some(p) if p.control().rewinding() => {
+                p.frame();
+                p.control().clone()
+            },
+            _ => return false,
+        };
+        if !self.rewind_step() {
+            info!("no rewind history left");
+            control.set_rewinding(false);
+        }
+        self.controls.timing_frame = Some(self.memory.frames());
+        true
+    }
+}
@@ -0,0 +1,139 @@
+use std::io;
+
+use super::*;
+
+const SB: usize = FF00 + 0x01;
+const SC: usize = FF00 + 0x02;
+const IF: usize = FF00 + 0x0F;
+
+const SERIAL_INTERRUPT: u8 = 0b0000_1000;
+const TRANSFER_START: u8 = 0b1000_0000;
+const INTERNAL_CLOCK: u8 = 0b0000_0001;
+
+/// 8 bits shifted at 8192Hz, it's also the lockstep period between linked consoles
+pub const SERIAL_TRANSFER_CYCLES: usize = 4096;
+
+/// line state seen by the peer, nothing plugged in reads as an idle line
+const DISCONNECTED: SerialLine = SerialLine { sb: 0xFF, sc: 0x0 };
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct SerialLine {
+    pub sb: u8,
+    pub sc: u8,
+}
+
+impl SerialLine {
+    pub fn transferring(&self) -> bool {
+        self.sc & TRANSFER_START == TRANSFER_START
+    }
+
+    /// transfer requested driving the clock
+    pub fn master(&self) -> bool {
+        self.transferring() && self.sc & INTERNAL_CLOCK == INTERNAL_CLOCK
+    }
+
+    /// transfer requested waiting for the peer clock
+    pub fn slave(&self) -> bool {
+        self.transferring() && self.sc & INTERNAL_CLOCK == 0x0
+    }
+}
+
+pub trait SerialDevice {
+    // method called once every SERIAL_TRANSFER_CYCLES with the local line state,
+    // it must block until the peer line state is known
+    fn sync(&mut self, local: SerialLine) -> Result<SerialLine, io::Error>;
+}
+
+impl Cpu {
+    pub fn connect_serial(&mut self, device: Box<dyn SerialDevice>) {
+        self.serial = Some(device);
+    }
+
+    pub(crate) fn reset_serial(&mut self) {
+        self.controls.serial_control = self.registers.clock_cycles();
+    }
+
+    pub(crate) fn serial_tick(&mut self) {
+        let cc_elapsed = self.registers.clock_cycles() - self.controls.serial_control;
+        if cc_elapsed < SERIAL_TRANSFER_CYCLES {
+            return;
+        }
+        // keeping a fixed grid so both ends sync on the same transfer periods
+        self.controls.serial_control += SERIAL_TRANSFER_CYCLES;
+
+        let local = SerialLine {
+            sb: self.memory[SB],
+            sc: self.memory[SC],
+        };
+        let peer = match &mut self.serial {
+            Some(d) => match d.sync(local) {
+                Ok(p) => p,
+                Err(e) => {
+                    error!("serial link lost => {}", e);
+                    self.serial = None;
+                    DISCONNECTED
+                },
+            },
+            None => DISCONNECTED,
+        };
+        trace!("serial sync local:{:?} peer:{:?}", local, peer);
+
+        let received = if local.master() {
+            // a peer not waiting for our clock shifts in an idle line
+            Some(if peer.slave() { peer.sb } else { 0xFF })
+        } else if local.slave() && peer.master() {
+            Some(peer.sb)
+        } else {
+            None
+        };
+
+        if let Some(value) = received {
+            if local.master() {
+                self.controls.serial_output.push(local.sb);
+            }
+            debug!("serial transfer sent:{:#x} received:{:#x}", local.sb, value);
+            self.memory.write(SB, value);
+            self.memory.write(SC, local.sc & !TRANSFER_START);
+            let flags = self.memory[IF] | SERIAL_INTERRUPT;
+            self.memory.write(IF, flags);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::link::{self, LinkCable};
+    use std::thread;
+
+    /// LD A,sb  LDH (SB),A  LD A,sc  LDH (SC),A  JR -2
+    fn transfer(sb: u8, sc: u8) -> Vec<u8> {
+        vec![0x3E, sb, 0xE0, 0x01, 0x3E, sc, 0xE0, 0x02, 0x18, 0xFE]
+    }
+
+    fn console(program: Vec<u8>, cable: LinkCable) -> thread::JoinHandle<(u8, Vec<u8>)> {
+        thread::spawn(move || {
+            let mut rom = vec![0; 0x8000];
+            rom[ROM_INITIAL_ADDRESS..ROM_INITIAL_ADDRESS + program.len()].copy_from_slice(&program);
+            let mut cpu = initialize(rom, None).unwrap();
+            cpu.bootup(None);
+            cpu.set_limits(RunLimits { cycles: Some(3 * SERIAL_TRANSFER_CYCLES), ..RunLimits::default() });
+            cpu.connect_serial(Box::new(cable));
+            cpu.run();
+            (cpu.memory[SB], cpu.serial_output().to_vec())
+        })
+    }
+
+    #[test]
+    fn test_linked_consoles_exchange_bytes() {
+        let (a, b) = link::pair().unwrap();
+        let master = console(transfer(0x42, TRANSFER_START | INTERNAL_CLOCK), a);
+        let slave = console(transfer(0x99, TRANSFER_START), b);
+        let (master_sb, master_sent) = master.join().unwrap();
+        let (slave_sb, _) = slave.join().unwrap();
+
+        assert_eq!(master_sb, 0x99);
+        assert_eq!(slave_sb, 0x42);
+        assert_eq!(master_sent.first(), Some(&0x42));
+    }
+}
@@ -0,0 +1,89 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::*;
+use super::battery::write_atomic;
+use super::super::state::*;
+
+impl Cpu {
+    /// header a state of the running machine carries, and loaded ones must match
+    pub fn state_header(&mut self) -> StateHeader {
+        if self.controls.rom_sha1.is_none() {
+            self.controls.rom_sha1 = Some(self.memory.cartridge_sha1());
+        }
+        let sha1 = self.controls.rom_sha1.clone().unwrap_or_default();
+        StateHeader::new(self.memory.model(), sha1)
+    }
+
+    /// machine state without header: registers, timers, memory and cartridge
+    pub(crate) fn save_machine(&self) -> Vec<u8> {
+        let mut w = StateWriter::default();
+        self.registers.save_state(&mut w);
+        let c = &self.controls;
+        w.usize(c.old_pc);
+        w.bool(c.game_booted);
+        w.usize(c.div_control);
+        w.usize(c.serial_control);
+        w.usize(c.cpu_clock);
+        w.usize(c.device_clock);
+        self.memory.save_state(&mut w);
+        w.into_bytes()
+    }
+
+    /// restores a save_machine dump, on failure the machine is left as it was
+    pub(crate) fn load_machine(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        let backup = self.save_machine();
+        let result = self.restore_machine(data);
+        if result.is_err() {
+            self.restore_machine(&backup).expect("restoring the running machine");
+        }
+        // the flush period starts over from the restored clock
+        self.controls.battery_control = self.registers.clock_cycles();
+        result
+    }
+
+    fn restore_machine(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        let mut r = StateReader::new(data);
+        self.registers.load_state(&mut r)?;
+        self.controls.old_pc = r.usize()?;
+        self.controls.game_booted = r.bool()?;
+        self.controls.div_control = r.usize()?;
+        self.controls.serial_control = r.usize()?;
+        self.controls.cpu_clock = r.usize()?;
+        self.controls.device_clock = r.usize()?;
+        self.memory.load_state(&mut r)?;
+        if !r.finished() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "trailing data after save state"));
+        }
+        Ok(())
+    }
+
+    pub fn save_state(&mut self) -> Vec<u8> {
+        let mut w = StateWriter::default();
+        self.state_header().write(&mut w);
+        w.bytes(&self.save_machine());
+        w.into_bytes()
+    }
+
+    /// refuses states from another rom, model or format version before touching anything
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        let mut r = StateReader::new(data);
+        let header = StateHeader::read(&mut r)?;
+        header.check(&self.state_header())?;
+        let machine = r.bytes()?;
+        self.load_machine(machine)
+    }
+
+    pub fn save_state_file(&mut self, path: &Path) -> Result<(), io::Error> {
+        write_atomic(path, &self.save_state())?;
+        info!("state saved to {:?}", path);
+        Ok(())
+    }
+
+    pub fn load_state_file(&mut self, path: &Path) -> Result<(), io::Error> {
+        self.load_state(&fs::read(path)?)?;
+        info!("state loaded from {:?}", path);
+        Ok(())
+    }
+}
@@ -24,7 +24,8 @@ impl Cpu {
         let cc_elapsed = self.registers.clock_cycles() - self.controls.div_control;
         trace!("div => div_control:{} cc_elpased:{}", self.controls.div_control, cc_elapsed);
         if cc_elapsed >= div_frequency {
-            self.memory[FF00 + 04] = self.memory[FF00 + 04].wrapping_add(1);
+            let div = self.memory[FF00 + 04].wrapping_add(1);
+            self.memory.write(FF00 + 04, div);
             self.controls.div_control = self.registers.clock_cycles();
         }
     }
@@ -93,6 +94,7 @@ impl Cpu {
             OptCode::DI |
             OptCode::EI |
             OptCode::NOP |
+            OptCode::STOP |
             OptCode::SUBn(RegisterType::A) |
             OptCode::SUBn(RegisterType::B) |
             OptCode::SUBn(RegisterType::C) |
@@ -101,6 +103,7 @@ impl Cpu {
             OptCode::SUBn(RegisterType::H) |
             OptCode::SUBn(RegisterType::L) |
             OptCode::LDNn(RegisterType::A, RegisterType::A) |
+            OptCode::LDNn(RegisterType::B, RegisterType::B) |
             OptCode::LDNn(RegisterType::B, RegisterType::A) |
             OptCode::LDNn(RegisterType::C, RegisterType::A) |
             OptCode::LDNn(RegisterType::D, RegisterType::A) |
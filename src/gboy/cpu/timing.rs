@@ -0,0 +1,24 @@
+use super::*;
+use super::super::timing::{Pacer, TimingControl};
+
+impl Cpu {
+    /// paces the emulation on the wall clock from now on, frontends drive
+    /// speed, pause and frame advance through clones of the control
+    pub fn enable_timing(&mut self, control: TimingControl) {
+        info!("pacing at {:?} times real time", control.multiplier());
+        self.controls.pacer = Some(Pacer::new(control));
+        // the frame about to start waits too, a paused run doesn't run one by itself
+        self.controls.timing_frame = None;
+    }
+
+    pub(crate) fn timing_tick(&mut self) {
+        let frames = self.memory.frames();
+        if self.controls.timing_frame == Some(frames) || self.controls.quit {
+            return;
+        }
+        self.controls.timing_frame = Some(frames);
+        if let Some(p) = &mut self.controls.pacer {
+            p.frame();
+        }
+    }
+}
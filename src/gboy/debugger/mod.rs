@@ -40,6 +40,7 @@ struct Cpu {
 pub struct Debugger {
     terminal: Terminal<TermionBackend<AlternateScreen<termion::raw::RawTerminal<io::Stdout>>>>,
     cpu: Cpu,
+    cartridge: Vec<String>,
 }
 
 pub fn initialize() -> Result<impl CpuDebugger, io::Error> {
@@ -54,12 +55,14 @@ pub fn initialize() -> Result<impl CpuDebugger, io::Error> {
         Debugger {
             terminal: terminal,
             cpu: Cpu::default(),
+            cartridge: Vec::new(),
     })
 }
 
 impl Debugger {
     fn render(&mut self) -> Result<(), io::Error> {
         let cpu = self.cpu.clone();
+        let cartridge: Vec<Text> = self.cartridge.iter().map(|l| Text::raw(format!("{}\n", l))).collect();
         
         self.terminal.draw(|mut f| {
             let size = f.size();
@@ -117,6 +120,11 @@ impl Debugger {
                 .alignment(Alignment::Left)
                 .wrap(true)
                 .render(&mut f, chunks[1]);
+            Paragraph::new(cartridge.iter())
+                .block(block.title("CARTRIDGE"))
+                .alignment(Alignment::Left)
+                .wrap(true)
+                .render(&mut f, chunks[2]);
             // Paragraph::new(text.iter())
             //     .block(block.clone().title("Center, wrap"))
             //     .alignment(Alignment::Center)
@@ -172,6 +180,16 @@ impl CpuDebugger for Debugger {
                 
             },
             CpuDebuggerMessage::OptCode(v) => self.cpu.opcode = v,
+            CpuDebuggerMessage::Cartridge(h) => {
+                self.cartridge = vec![
+                    format!("Title => {}", h.title),
+                    format!("Type => {}", h.cartridge_type_name()),
+                    format!("ROM/RAM => {}KiB/{}KiB", h.rom_size / 1024, h.ram_size / 1024),
+                    format!("CGB/SGB => {:?}/{}", h.cgb, h.sgb),
+                    format!("Licensee => {}", h.licensee()),
+                    format!("Version => {}", h.version),
+                ];
+            },
             CpuDebuggerMessage::Display{stat, wy, wx, ly, lyc, scy, scx } =>{
                 self.cpu.dis_stat = stat;
                 self.cpu.dis_wy = wy;
@@ -0,0 +1,78 @@
+use std::io;
+use std::io::prelude::*;
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use super::cpu::*;
+
+const HANDSHAKE: [u8; 4] = [b'G', b'B', b'L', 0x1];
+
+trait LinkStream: Read + Write + Send {}
+impl<T: Read + Write + Send> LinkStream for T {}
+
+/// Serial device exchanging line states with another console over a socket,
+/// both ends block on every transfer period keeping the emulation in lockstep
+pub struct LinkCable {
+    stream: Box<dyn LinkStream>,
+}
+
+fn is_tcp(address: &str) -> bool {
+    address.contains(':')
+}
+
+/// waits for a peer on address, "host:port" for TCP, anything else is a unix socket path
+pub fn listen(address: &str) -> Result<LinkCable, io::Error> {
+    info!("waiting for link cable peer on {}", address);
+    let stream: Box<dyn LinkStream> = if is_tcp(address) {
+        let (s, peer) = TcpListener::bind(address)?.accept()?;
+        info!("link cable peer connected from {}", peer);
+        s.set_nodelay(true)?;
+        Box::new(s)
+    } else {
+        let (s, _) = UnixListener::bind(address)?.accept()?;
+        Box::new(s)
+    };
+    LinkCable::handshake(stream)
+}
+
+/// connects to a peer already listening on address
+pub fn connect(address: &str) -> Result<LinkCable, io::Error> {
+    info!("connecting link cable to {}", address);
+    let stream: Box<dyn LinkStream> = if is_tcp(address) {
+        let s = TcpStream::connect(address)?;
+        s.set_nodelay(true)?;
+        Box::new(s)
+    } else {
+        Box::new(UnixStream::connect(address)?)
+    };
+    LinkCable::handshake(stream)
+}
+
+/// two cables plugged into each other, for consoles running on the same process
+pub fn pair() -> Result<(LinkCable, LinkCable), io::Error> {
+    let (a, b) = UnixStream::pair()?;
+    Ok((LinkCable { stream: Box::new(a) }, LinkCable { stream: Box::new(b) }))
+}
+
+impl LinkCable {
+    fn handshake(mut stream: Box<dyn LinkStream>) -> Result<LinkCable, io::Error> {
+        stream.write_all(&HANDSHAKE)?;
+        stream.flush()?;
+        let mut peer = [0; 4];
+        stream.read_exact(&mut peer)?;
+        if peer != HANDSHAKE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected link cable handshake => {:?}", peer)));
+        }
+        Ok(LinkCable { stream })
+    }
+}
+
+impl SerialDevice for LinkCable {
+    fn sync(&mut self, local: SerialLine) -> Result<SerialLine, io::Error> {
+        self.stream.write_all(&[local.sb, local.sc])?;
+        self.stream.flush()?;
+        let mut peer = [0; 2];
+        self.stream.read_exact(&mut peer)?;
+        Ok(SerialLine { sb: peer[0], sc: peer[1] })
+    }
+}
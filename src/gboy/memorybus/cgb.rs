@@ -0,0 +1,181 @@
+use super::super::model::Model;
+use super::color::*;
+use super::hdma::*;
+use super::memory::*;
+
+pub(super) const VRAM_BANK_SIZE: usize = 0x2000;
+pub(super) const WRAM_BANK_SIZE: usize = 0x1000;
+
+pub(super) const KEY1: usize = 0xFF4D;
+pub(super) const VBK: usize = 0xFF4F;
+pub(super) const SVBK: usize = 0xFF70;
+
+const PREPARE_SPEED_SWITCH: u8 = 0b0000_0001;
+const DOUBLE_SPEED: u8 = 0b1000_0000;
+
+/// registers only wired on CGB, they read 0xFF and ignore writes on DMG/SGB
+pub(super) fn cgb_register(address: usize) -> bool {
+    matches!(address, 0xFF4D | 0xFF4F | 0xFF51..=0xFF56 | 0xFF68..=0xFF6C | 0xFF70 | 0xFF72..=0xFF77)
+}
+
+impl Memory {
+    /// registers state left by the boot rom of the current model
+    pub(super) fn reset_cgb_registers(&mut self) {
+        for address in (0xFF00..=0xFF7F).filter(|a| cgb_register(*a)) {
+            self.ram[address] = 0xFF;
+        }
+        if self.model().is_cgb() {
+            self.ram[KEY1] = 0x7E;
+            self.ram[VBK] = 0xFE;
+            self.ram[SVBK] = 0xF8;
+            // OAM order sprite priority, compatibility mode boot roms pick the DMG one
+            self.ram[OPRI] = if self.model() == Model::Cgb { 0xFE } else { 0xFF };
+            self.ram[BCPS] = 0x40;
+            self.ram[OCPS] = 0x40;
+            self.sync_palette_data();
+        }
+    }
+
+    /// writes on CGB only registers, returns false when the address isn't one
+    pub(super) fn cgb_write(&mut self, address: usize, value: u8) -> bool {
+        if !cgb_register(address) {
+            return false;
+        }
+        if !self.model().is_cgb() {
+            trace!("CGB register write ignored {:#x} => {:#x}", address, value);
+            return true;
+        }
+        match address {
+            // unused bits read back as 1s
+            KEY1 => self.ram[KEY1] = (self.ram[KEY1] & DOUBLE_SPEED) | 0x7E | (value & PREPARE_SPEED_SWITCH),
+            VBK => self.ram[VBK] = 0xFE | (value & 0b1),
+            SVBK => self.ram[SVBK] = 0xF8 | (value & 0b111),
+            HDMA1..=HDMA5 => self.hdma_write(address, value),
+            BCPS | BCPD | OCPS | OCPD => self.palette_write(address, value),
+            OPRI => self.ram[OPRI] = 0xFE | (value & 0b1),
+            _ => self.ram[address] = value,
+        }
+        true
+    }
+
+    /// cpu side vram bank, DMG and SGB only have bank 0 while VBK reads 0xFF
+    pub(super) fn vram_bank(&self) -> usize {
+        if !self.model().is_cgb() {
+            return 0;
+        }
+        (self.ram[VBK] & 0b1) as usize
+    }
+
+    /// 0xD000..=0xDFFF bank, 0 maps bank 1 as well
+    pub(super) fn wram_bank(&self) -> usize {
+        if !self.model().is_cgb() {
+            return 1;
+        }
+        match self.ram[SVBK] & 0b111 {
+            0 => 1,
+            b => b as usize,
+        }
+    }
+
+    /// offset on the wram banks for 0xC000..=0xFDFF, echo ram included
+    pub(super) fn wram_offset(&self, address: usize) -> usize {
+        let address = if address >= 0xE000 { address - 0x2000 } else { address };
+        match address {
+            0xC000..=0xCFFF => address - 0xC000,
+            _ => self.wram_bank() * WRAM_BANK_SIZE + address - 0xD000,
+        }
+    }
+
+    pub(super) fn vram_offset(&self, address: usize) -> usize {
+        self.vram_bank() * VRAM_BANK_SIZE + address - 0x8000
+    }
+
+    /// vram byte on a given bank, as the gpu sees it
+    pub(super) fn vram(&self, bank: usize, address: usize) -> u8 {
+        self.vram[bank * VRAM_BANK_SIZE + address - 0x8000]
+    }
+
+    pub fn double_speed(&self) -> bool {
+        self.ram[KEY1] & DOUBLE_SPEED != 0x0 && self.model().is_cgb()
+    }
+
+    /// STOP with KEY1 armed switches cpu speed, returns whether it did
+    pub fn switch_speed(&mut self) -> bool {
+        if !self.model().is_cgb() || self.ram[KEY1] & PREPARE_SPEED_SWITCH == 0x0 {
+            return false;
+        }
+        self.ram[KEY1] = (self.ram[KEY1] ^ DOUBLE_SPEED) & !PREPARE_SPEED_SWITCH;
+        debug!("cpu speed switched, double speed:{}", self.double_speed());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::cartridge;
+
+    fn memory(model: Model) -> Memory {
+        let mut memory = new(cartridge::new(vec![0; 0x8000]).unwrap());
+        memory.set_model(model);
+        memory
+    }
+
+    #[test]
+    fn test_dmg_vram_writes_land_on_bank_0() {
+        for model in [Model::Dmg, Model::Sgb] {
+            let mut memory = memory(model);
+            memory.write(VBK, 0x1);
+            memory.write(0x8000_usize, 0xAB);
+            assert_eq!(memory[VBK], 0xFF);
+            assert_eq!(memory.vram(0, 0x8000), 0xAB);
+            assert_eq!(memory[0x8000_usize], 0xAB);
+        }
+    }
+
+    #[test]
+    fn test_cgb_vram_bank_select() {
+        let mut memory = memory(Model::Cgb);
+        memory.write(0x8000_usize, 0x11);
+        memory.write(VBK, 0x1);
+        memory.write(0x8000_usize, 0x22);
+        assert_eq!(memory.vram(0, 0x8000), 0x11);
+        assert_eq!(memory.vram(1, 0x8000), 0x22);
+    }
+
+    #[test]
+    fn test_cgb_wram_bank_select() {
+        let mut memory = memory(Model::Cgb);
+        for bank in 1..=7u8 {
+            memory.write(SVBK, bank);
+            memory.write(0xD000_usize, bank);
+            memory.write(0xDFFF_usize, 0x10 | bank);
+        }
+        for bank in 1..=7u8 {
+            memory.write(SVBK, bank);
+            assert_eq!(memory[SVBK], 0xF8 | bank);
+            assert_eq!(memory[0xD000_usize], bank);
+            assert_eq!(memory[0xDFFF_usize], 0x10 | bank);
+        }
+        // bank 0 maps bank 1, 0xC000..=0xCFFF never switches
+        memory.write(0xC000_usize, 0xCC);
+        memory.write(SVBK, 0x0);
+        assert_eq!(memory[SVBK], 0xF8);
+        assert_eq!(memory[0xD000_usize], 1);
+        memory.write(SVBK, 0x5);
+        assert_eq!(memory[0xC000_usize], 0xCC);
+    }
+
+    #[test]
+    fn test_cgb_registers_read_ff_on_dmg() {
+        let mut memory = memory(Model::Dmg);
+        for address in [KEY1, VBK, SVBK, HDMA1, HDMA2, HDMA3, HDMA4, HDMA5] {
+            memory.write(address, 0x01);
+            assert_eq!(memory[address], 0xFF, "{:#06x}", address);
+        }
+        assert!(!memory.switch_speed());
+        // without SVBK 0xD000 is a single bank
+        memory.write(0xD000_usize, 0x42);
+        assert_eq!(memory[0xD000_usize], 0x42);
+    }
+}
@@ -0,0 +1,363 @@
+use super::super::model::Model;
+use super::gpu::*;
+use super::memory::*;
+use super::super::state::{StateReader, StateWriter};
+use std::io;
+
+pub(super) const BCPS: usize = 0xFF68;
+pub(super) const BCPD: usize = 0xFF69;
+pub(super) const OCPS: usize = 0xFF6A;
+pub(super) const OCPD: usize = 0xFF6B;
+pub(super) const OPRI: usize = 0xFF6C;
+
+const LCDC: usize = 0xFF40;
+const SCY: usize = 0xFF42;
+const SCX: usize = 0xFF43;
+const WY: usize = 0xFF4A;
+const WX: usize = 0xFF4B;
+
+const AUTO_INCREMENT: u8 = 0b1000_0000;
+const PALETTE_RAM_SIZE: usize = 64;
+
+/// white to black, as RGB555, what compatibility mode palettes start with
+const GREYS: [u16; 4] = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+
+/// CGB palette ram, 8 palettes of 4 RGB555 little endian colors for each of
+/// BG and OBJ, reached through an index register with optional auto increment
+pub struct Palettes {
+    bg: [u8; PALETTE_RAM_SIZE],
+    obj: [u8; PALETTE_RAM_SIZE],
+}
+
+impl Default for Palettes {
+    fn default() -> Palettes {
+        let mut palettes = Palettes {
+            bg: [0xFF; PALETTE_RAM_SIZE],
+            obj: [0xFF; PALETTE_RAM_SIZE],
+        };
+        for p in 0..8 {
+            for (c, grey) in GREYS.iter().enumerate() {
+                for ram in [&mut palettes.bg, &mut palettes.obj].iter_mut() {
+                    ram[p * 8 + c * 2] = *grey as u8;
+                    ram[p * 8 + c * 2 + 1] = (*grey >> 8) as u8;
+                }
+            }
+        }
+        palettes
+    }
+}
+
+impl Palettes {
+    pub(super) fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.bg);
+        w.bytes(&self.obj);
+    }
+
+    pub(super) fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        r.bytes_into(&mut self.bg)?;
+        r.bytes_into(&mut self.obj)
+    }
+}
+
+fn color(ram: &[u8; PALETTE_RAM_SIZE], palette: u8, color: u8) -> u16 {
+    let i = (palette as usize & 0b111) * 8 + (color as usize & 0b11) * 2;
+    (ram[i] as u16 | (ram[i + 1] as u16) << 8) & 0x7FFF
+}
+
+impl Memory {
+    /// BCPS/BCPD/OCPS/OCPD writes
+    pub(super) fn palette_write(&mut self, address: usize, value: u8) {
+        match address {
+            // bit 6 is unused and reads 1
+            BCPS | OCPS => self.ram[address] = value | 0b0100_0000,
+            BCPD => {
+                let index = (self.ram[BCPS] & 0x3F) as usize;
+                self.gpu.palettes.bg[index] = value;
+                self.increment_palette_index(BCPS);
+            },
+            OCPD => {
+                let index = (self.ram[OCPS] & 0x3F) as usize;
+                self.gpu.palettes.obj[index] = value;
+                self.increment_palette_index(OCPS);
+            },
+            _ => {},
+        }
+        self.sync_palette_data();
+    }
+
+    /// data registers read back the byte the index points at
+    pub(super) fn sync_palette_data(&mut self) {
+        self.ram[BCPD] = self.gpu.palettes.bg[(self.ram[BCPS] & 0x3F) as usize];
+        self.ram[OCPD] = self.gpu.palettes.obj[(self.ram[OCPS] & 0x3F) as usize];
+    }
+
+    fn increment_palette_index(&mut self, register: usize) {
+        let spec = self.ram[register];
+        if spec & AUTO_INCREMENT != 0 {
+            self.ram[register] = (spec & 0xC0) | (((spec & 0x3F) + 1) & 0x3F);
+        }
+    }
+
+    pub(super) fn bg_color(&self, palette: u8, index: u8) -> u16 {
+        color(&self.gpu.palettes.bg, palette, index)
+    }
+
+    pub(super) fn obj_color(&self, palette: u8, index: u8) -> u16 {
+        color(&self.gpu.palettes.obj, palette, index)
+    }
+
+    /// RGB555 framebuffer, only drawn on CGB
+    pub fn color_framebuffer(&self) -> Option<&[u16]> {
+        if self.model().is_cgb() {
+            Some(&self.gpu.colors)
+        } else {
+            None
+        }
+    }
+
+    fn cgb_tile_pixel(&self, bank: usize, tile_data_signed: bool, tile: u8, x: usize, y: usize) -> u8 {
+        let base = if tile_data_signed {
+            (0x9000 + (tile as i8 as isize) * 16) as usize
+        } else {
+            0x8000 + tile as usize * 16
+        };
+        let lo = self.vram(bank, base + y * 2);
+        let hi = self.vram(bank, base + y * 2 + 1);
+        let bit = 7 - x;
+        (((hi >> bit) & 0b1) << 1) | ((lo >> bit) & 0b1)
+    }
+
+    /// BG/window pixel through a tile map, returns the color index and attributes
+    fn cgb_map_pixel(&self, map: usize, signed: bool, x: usize, y: usize) -> (u8, u8) {
+        let at = map + (y / 8) * 32 + x / 8;
+        let tile = self.vram(0, at);
+        let attributes = self.vram(1, at);
+        let bank = ((attributes >> 3) & 0b1) as usize;
+        let tx = if attributes & 0b0010_0000 != 0 { 7 - x % 8 } else { x % 8 };
+        let ty = if attributes & 0b0100_0000 != 0 { 7 - y % 8 } else { y % 8 };
+        (self.cgb_tile_pixel(bank, signed, tile, tx, ty), attributes)
+    }
+
+    /// CGB mode line, BG attributes from vram bank 1 and colors from palette ram
+    pub(super) fn render_cgb_line(&mut self, ly: usize) {
+        let lcdc = self.ram[LCDC];
+        let signed = lcdc & 0b0001_0000 == 0;
+        // color index and attributes per pixel
+        let mut bg = [(0u8, 0u8); SCREEN_WIDTH];
+
+        let map = if lcdc & 0b0000_1000 != 0 { 0x9C00 } else { 0x9800 };
+        let y = (ly + self.ram[SCY] as usize) & 0xFF;
+        for (x, pixel) in bg.iter_mut().enumerate() {
+            let px = (x + self.ram[SCX] as usize) & 0xFF;
+            *pixel = self.cgb_map_pixel(map, signed, px, y);
+        }
+
+        // window, LCDC bit 0 doesn't hide it on CGB
+        let wy = self.ram[WY] as usize;
+        let wx = self.ram[WX] as usize;
+        if lcdc & 0b0010_0000 != 0 && ly >= wy && wx <= 166 {
+            let map = if lcdc & 0b0100_0000 != 0 { 0x9C00 } else { 0x9800 };
+            let y = self.gpu.window_line;
+            for (x, pixel) in bg.iter_mut().enumerate() {
+                if x + 7 < wx {
+                    continue;
+                }
+                *pixel = self.cgb_map_pixel(map, signed, x + 7 - wx, y);
+            }
+            self.gpu.window_line += 1;
+        }
+
+        for (x, (index, attributes)) in bg.iter().enumerate() {
+            self.gpu.colors[ly * SCREEN_WIDTH + x] = self.bg_color(attributes & 0b111, *index);
+            self.gpu.framebuffer[ly * SCREEN_WIDTH + x] = *index;
+        }
+
+        if lcdc & 0b0000_0010 != 0 {
+            self.render_cgb_sprites(ly, &bg);
+        }
+    }
+
+    fn render_cgb_sprites(&mut self, ly: usize, bg: &[(u8, u8)]) {
+        let lcdc = self.ram[LCDC];
+        let height = if lcdc & 0b0000_0100 != 0 { 16 } else { 8 };
+        // LCDC bit 0 off on CGB puts sprites over everything
+        let bg_priority = lcdc & 0b0000_0001 != 0;
+
+        let mut sprites: Vec<usize> = (0..40)
+            .map(|i| 0xFE00 + i * 4)
+            .filter(|&s| {
+                let y = self.ram[s] as isize - 16;
+                (ly as isize) >= y && (ly as isize) < y + height
+            })
+            .take(10)
+            .collect();
+        // OAM order wins on CGB, unless OPRI asks for the DMG x ordering
+        if self.ram[OPRI] & 0b1 != 0 {
+            sprites.sort_by_key(|&s| self.ram[s + 1]);
+        }
+        sprites.reverse();
+
+        for s in sprites {
+            let y = self.ram[s] as isize - 16;
+            let x = self.ram[s + 1] as isize - 8;
+            let mut tile = self.ram[s + 2];
+            let attributes = self.ram[s + 3];
+            let bank = ((attributes >> 3) & 0b1) as usize;
+
+            let mut row = ly as isize - y;
+            if attributes & 0b0100_0000 != 0 {
+                row = height - 1 - row;
+            }
+            if height == 16 {
+                tile &= 0xFE;
+            }
+            for col in 0..8 {
+                let px = x + col;
+                if px < 0 || px >= SCREEN_WIDTH as isize {
+                    continue;
+                }
+                let tx = if attributes & 0b0010_0000 != 0 { 7 - col } else { col };
+                let color = self.cgb_tile_pixel(bank, false, tile, tx as usize, row as usize);
+                if color == 0 {
+                    continue;
+                }
+                let (bg_index, bg_attributes) = bg[px as usize];
+                let behind = attributes & 0b1000_0000 != 0 || bg_attributes & 0b1000_0000 != 0;
+                if bg_priority && behind && bg_index != 0 {
+                    continue;
+                }
+                let at = ly * SCREEN_WIDTH + px as usize;
+                self.gpu.colors[at] = self.obj_color(attributes & 0b111, color);
+                self.gpu.framebuffer[at] = color;
+            }
+        }
+    }
+
+    /// compatibility mode colors a DMG pixel through BG palette 0 or OBJ palettes 0/1
+    pub(super) fn compat_color(&mut self, at: usize, obj_palette: Option<u8>, shade: u8) {
+        if self.model() != Model::CgbCompat {
+            return;
+        }
+        self.gpu.colors[at] = match obj_palette {
+            Some(p) => self.obj_color(p, shade),
+            None => self.bg_color(0, shade),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::cgb::VBK;
+    use super::super::super::cartridge;
+
+    fn memory() -> Memory {
+        let mut memory = new(cartridge::new(vec![0; 0x8000]).unwrap());
+        memory.set_model(Model::Cgb);
+        memory
+    }
+
+    fn write(memory: &mut Memory, address: usize, value: u8) {
+        memory.write(address, value);
+    }
+
+    /// tile 0 on bank 1 has color 1 on the top left pixel and color 2 on the
+    /// bottom left one, tile 1 on bank 0 is all color 3
+    fn tiles(memory: &mut Memory) {
+        write(memory, VBK, 0x1);
+        write(memory, 0x8000, 0x80);
+        write(memory, 0x800F, 0x80);
+        write(memory, VBK, 0x0);
+        write(memory, 0x8010, 0xFF);
+        write(memory, 0x8011, 0xFF);
+    }
+
+    fn bg_attributes(memory: &mut Memory, attributes: u8) {
+        write(memory, VBK, 0x1);
+        write(memory, 0x9800, attributes);
+        write(memory, VBK, 0x0);
+    }
+
+    #[test]
+    fn test_palette_auto_increment() {
+        let mut memory = memory();
+        write(&mut memory, BCPS, AUTO_INCREMENT | 0x3E);
+        write(&mut memory, BCPD, 0x12);
+        write(&mut memory, BCPD, 0x34);
+        // wraps around, bit 6 reads 1
+        assert_eq!(memory[BCPS], 0xC0);
+        assert_eq!(memory.bg_color(7, 3), 0x3412);
+
+        // without auto increment both writes land on the same byte
+        write(&mut memory, OCPS, 0x08);
+        write(&mut memory, OCPD, 0x1F);
+        write(&mut memory, OCPD, 0x00);
+        assert_eq!(memory[OCPS], 0x48);
+        assert_eq!(memory.obj_color(1, 0), 0x7F00);
+    }
+
+    #[test]
+    fn test_palette_data_read_back() {
+        let mut memory = memory();
+        write(&mut memory, BCPS, 0x02);
+        assert_eq!(memory[BCPD], (GREYS[1] & 0xFF) as u8);
+        write(&mut memory, BCPD, 0x5A);
+        assert_eq!(memory[BCPD], 0x5A);
+        write(&mut memory, BCPS, 0x03);
+        assert_eq!(memory[BCPD], (GREYS[1] >> 8) as u8);
+        write(&mut memory, OCPS, AUTO_INCREMENT);
+        write(&mut memory, OCPD, 0x77);
+        write(&mut memory, OCPS, 0x00);
+        assert_eq!(memory[OCPD], 0x77);
+    }
+
+    #[test]
+    fn test_bg_attributes_bank_and_flips() {
+        let mut memory = memory();
+        tiles(&mut memory);
+        write(&mut memory, LCDC, 0x91);
+
+        let line = |memory: &mut Memory, attributes: u8, ly: usize| {
+            bg_attributes(memory, attributes);
+            memory.render_cgb_line(ly);
+            memory.gpu.framebuffer[ly * SCREEN_WIDTH..ly * SCREEN_WIDTH + 8].to_vec()
+        };
+        assert_eq!(line(&mut memory, 0x00, 0), [0; 8]);
+        assert_eq!(line(&mut memory, 0x08, 0), [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(line(&mut memory, 0x28, 0), [0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(line(&mut memory, 0x48, 0), [2, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(line(&mut memory, 0x68, 7), [0, 0, 0, 0, 0, 0, 0, 1]);
+
+        // palette number from the attributes
+        write(&mut memory, BCPS, AUTO_INCREMENT | (5 * 8 + 2));
+        write(&mut memory, BCPD, 0x1F);
+        write(&mut memory, BCPD, 0x00);
+        line(&mut memory, 0x0D, 0);
+        assert_eq!(memory.gpu.colors[0], 0x001F);
+        assert_eq!(memory.gpu.colors[1], GREYS[0]);
+    }
+
+    #[test]
+    fn test_obj_priority() {
+        let mut memory = memory();
+        tiles(&mut memory);
+        // sprite using tile 1 on the top left corner
+        write(&mut memory, 0xFE00, 16);
+        write(&mut memory, 0xFE01, 8);
+        write(&mut memory, 0xFE02, 1);
+
+        let line = |memory: &mut Memory, lcdc: u8, bg: u8, obj: u8| {
+            write(memory, LCDC, lcdc);
+            bg_attributes(memory, bg);
+            write(memory, 0xFE03, obj);
+            memory.render_cgb_line(0);
+            memory.gpu.framebuffer[0..2].to_vec()
+        };
+        assert_eq!(line(&mut memory, 0x93, 0x08, 0x00), [3, 3]);
+        // behind non zero BG colors only, by OAM or BG attribute
+        assert_eq!(line(&mut memory, 0x93, 0x08, 0x80), [1, 3]);
+        assert_eq!(line(&mut memory, 0x93, 0x88, 0x00), [1, 3]);
+        // LCDC bit 0 clear puts sprites over everything
+        assert_eq!(line(&mut memory, 0x92, 0x88, 0x80), [3, 3]);
+    }
+}
@@ -0,0 +1,322 @@
+use super::super::model::Model;
+use super::color::Palettes;
+use super::memory::*;
+use super::super::state::{StateReader, StateWriter};
+use std::io;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// clock cycles needed to draw a whole frame, lcd on or off
+pub const FRAME_CYCLES: usize = 70224;
+
+const LINE_CYCLES: usize = 456;
+const OAM_CYCLES: usize = 80;
+const TRANSFER_CYCLES: usize = 172;
+const LINES: u8 = 154;
+
+const LCDC: usize = 0xFF40;
+const STAT: usize = 0xFF41;
+const SCY: usize = 0xFF42;
+const SCX: usize = 0xFF43;
+const LY: usize = 0xFF44;
+const LYC: usize = 0xFF45;
+const BGP: usize = 0xFF47;
+const OBP0: usize = 0xFF48;
+const OBP1: usize = 0xFF49;
+const WY: usize = 0xFF4A;
+const WX: usize = 0xFF4B;
+const IF: usize = 0xFF0F;
+
+const VBLANK_INTERRUPT: u8 = 0b0000_0001;
+const STAT_INTERRUPT: u8 = 0b0000_0010;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GpuMode {
+    HBlank = 0,
+    VBlank = 1,
+    Oam = 2,
+    Transfer = 3,
+}
+
+pub struct Gpu {
+    pub(super) framebuffer: Vec<u8>,
+    // RGB555, drawn on CGB only
+    pub(super) colors: Vec<u16>,
+    pub(super) palettes: Palettes,
+    mode: GpuMode,
+    line_clock: usize,
+    frame_clock: usize,
+    last_clock: usize,
+    pub(super) window_line: usize,
+    frames: usize,
+}
+
+impl Default for Gpu {
+    fn default() -> Gpu {
+        Gpu {
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            colors: vec![0x7FFF; SCREEN_WIDTH * SCREEN_HEIGHT],
+            palettes: Palettes::default(),
+            mode: GpuMode::Oam,
+            line_clock: 0,
+            frame_clock: 0,
+            last_clock: 0,
+            window_line: 0,
+            frames: 0,
+        }
+    }
+}
+
+fn mode_from(value: u8) -> GpuMode {
+    match value & 0b11 {
+        0 => GpuMode::HBlank,
+        1 => GpuMode::VBlank,
+        2 => GpuMode::Oam,
+        _ => GpuMode::Transfer,
+    }
+}
+
+impl Gpu {
+    pub(super) fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.framebuffer);
+        w.u16s(&self.colors);
+        self.palettes.save_state(w);
+        w.u8(self.mode as u8);
+        w.usize(self.line_clock);
+        w.usize(self.frame_clock);
+        w.usize(self.last_clock);
+        w.usize(self.window_line);
+        w.usize(self.frames);
+    }
+
+    pub(super) fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        r.bytes_into(&mut self.framebuffer)?;
+        r.u16s_into(&mut self.colors)?;
+        self.palettes.load_state(r)?;
+        self.mode = mode_from(r.u8()?);
+        self.line_clock = r.usize()?;
+        self.frame_clock = r.usize()?;
+        self.last_clock = r.usize()?;
+        self.window_line = r.usize()?;
+        self.frames = r.usize()?;
+        Ok(())
+    }
+}
+
+impl Memory {
+    /// shades (0 to 3) already mapped through BGP/OBPx, one byte per pixel
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.gpu.framebuffer
+    }
+
+    pub fn frames(&self) -> usize {
+        self.gpu.frames
+    }
+
+    /// walks the gpu up to the cpu clock_cycles count
+    pub fn gpu_tick(&mut self, clock_cycles: usize) {
+        let elapsed = clock_cycles - self.gpu.last_clock;
+        self.gpu.last_clock = clock_cycles;
+
+        self.gpu.frame_clock += elapsed;
+        if self.gpu.frame_clock >= FRAME_CYCLES {
+            self.gpu.frame_clock -= FRAME_CYCLES;
+            self.gpu.frames += 1;
+        }
+
+        if !self.lcd_enabled() {
+            self.gpu.line_clock = 0;
+            self.gpu.window_line = 0;
+            self.ram[LY] = 0;
+            self.set_gpu_mode(GpuMode::HBlank);
+            return;
+        }
+
+        self.gpu.line_clock += elapsed;
+        loop {
+            let ly = self.ram[LY];
+            match self.gpu.mode {
+                GpuMode::Oam if self.gpu.line_clock >= OAM_CYCLES => {
+                    self.set_gpu_mode(GpuMode::Transfer);
+                },
+                GpuMode::Transfer if self.gpu.line_clock >= OAM_CYCLES + TRANSFER_CYCLES => {
+                    self.render_line(ly as usize);
+                    self.set_gpu_mode(GpuMode::HBlank);
+                    self.hdma_hblank();
+                },
+                GpuMode::HBlank | GpuMode::VBlank if self.gpu.line_clock >= LINE_CYCLES => {
+                    self.gpu.line_clock -= LINE_CYCLES;
+                    let next = (ly + 1) % LINES;
+                    self.set_ly(next);
+                    if next as usize == SCREEN_HEIGHT {
+                        self.ram[IF] |= VBLANK_INTERRUPT;
+                        self.set_gpu_mode(GpuMode::VBlank);
+                    } else if next as usize > SCREEN_HEIGHT {
+                        // still on vblank
+                    } else {
+                        if next == 0 {
+                            self.gpu.window_line = 0;
+                        }
+                        self.set_gpu_mode(GpuMode::Oam);
+                    }
+                },
+                _ => break,
+            }
+        }
+    }
+
+    pub(super) fn lcd_enabled(&self) -> bool {
+        self.ram[LCDC] & 0b1000_0000 != 0
+    }
+
+    fn set_ly(&mut self, ly: u8) {
+        self.ram[LY] = ly;
+        let coincidence = ly == self.ram[LYC];
+        if coincidence {
+            self.ram[STAT] |= 0b0000_0100;
+            if self.ram[STAT] & 0b0100_0000 != 0 {
+                self.ram[IF] |= STAT_INTERRUPT;
+            }
+        } else {
+            self.ram[STAT] &= !0b0000_0100;
+        }
+    }
+
+    fn set_gpu_mode(&mut self, mode: GpuMode) {
+        if self.gpu.mode == mode {
+            return;
+        }
+        trace!("gpu mode {:?} => {:?} ly:{}", self.gpu.mode, mode, self.ram[LY]);
+        self.gpu.mode = mode;
+        self.ram[STAT] = (self.ram[STAT] & !0b11) | mode as u8;
+        let source = match mode {
+            GpuMode::HBlank => 0b0000_1000,
+            GpuMode::VBlank => 0b0001_0000,
+            GpuMode::Oam => 0b0010_0000,
+            GpuMode::Transfer => 0,
+        };
+        if self.ram[STAT] & source != 0 {
+            self.ram[IF] |= STAT_INTERRUPT;
+        }
+    }
+
+    fn tile_pixel(&self, tile_data_signed: bool, tile: u8, x: usize, y: usize) -> u8 {
+        let base = if tile_data_signed {
+            (0x9000 + (tile as i8 as isize) * 16) as usize
+        } else {
+            0x8000 + tile as usize * 16
+        };
+        let lo = self.vram(0, base + y * 2);
+        let hi = self.vram(0, base + y * 2 + 1);
+        let bit = 7 - x;
+        (((hi >> bit) & 0b1) << 1) | ((lo >> bit) & 0b1)
+    }
+
+    fn shade(palette: u8, color: u8) -> u8 {
+        (palette >> (color * 2)) & 0b11
+    }
+
+    fn render_line(&mut self, ly: usize) {
+        if ly >= SCREEN_HEIGHT {
+            return;
+        }
+        if self.model() == Model::Cgb {
+            return self.render_cgb_line(ly);
+        }
+        let lcdc = self.ram[LCDC];
+        let bgp = self.ram[BGP];
+        let signed = lcdc & 0b0001_0000 == 0;
+        let mut colors = [0u8; SCREEN_WIDTH];
+
+        // background
+        if lcdc & 0b0000_0001 != 0 {
+            let map = if lcdc & 0b0000_1000 != 0 { 0x9C00 } else { 0x9800 };
+            let y = (ly + self.ram[SCY] as usize) & 0xFF;
+            for (x, color) in colors.iter_mut().enumerate() {
+                let px = (x + self.ram[SCX] as usize) & 0xFF;
+                let tile = self.vram(0, map + (y / 8) * 32 + px / 8);
+                *color = self.tile_pixel(signed, tile, px % 8, y % 8);
+            }
+        }
+
+        // window
+        let wy = self.ram[WY] as usize;
+        let wx = self.ram[WX] as usize;
+        if lcdc & 0b0010_0001 == 0b0010_0001 && ly >= wy && wx <= 166 {
+            let map = if lcdc & 0b0100_0000 != 0 { 0x9C00 } else { 0x9800 };
+            let y = self.gpu.window_line;
+            for (x, color) in colors.iter_mut().enumerate() {
+                if x + 7 < wx {
+                    continue;
+                }
+                let px = x + 7 - wx;
+                let tile = self.vram(0, map + (y / 8) * 32 + px / 8);
+                *color = self.tile_pixel(signed, tile, px % 8, y % 8);
+            }
+            self.gpu.window_line += 1;
+        }
+
+        for (x, color) in colors.iter().enumerate() {
+            let shade = Memory::shade(bgp, *color);
+            self.gpu.framebuffer[ly * SCREEN_WIDTH + x] = shade;
+            self.compat_color(ly * SCREEN_WIDTH + x, None, shade);
+        }
+
+        // sprites
+        if lcdc & 0b0000_0010 != 0 {
+            self.render_sprites(ly, &colors);
+        }
+    }
+
+    fn render_sprites(&mut self, ly: usize, bg_colors: &[u8]) {
+        let height = if self.ram[LCDC] & 0b0000_0100 != 0 { 16 } else { 8 };
+
+        // up to 10 sprites per line, picked on OAM order
+        let mut sprites: Vec<usize> = (0..40)
+            .map(|i| 0xFE00 + i * 4)
+            .filter(|&s| {
+                let y = self.ram[s] as isize - 16;
+                (ly as isize) >= y && (ly as isize) < y + height
+            })
+            .take(10)
+            .collect();
+        // lower x wins, drawing in reverse so the winner is painted last
+        sprites.sort_by_key(|&s| self.ram[s + 1]);
+        sprites.reverse();
+
+        for s in sprites {
+            let y = self.ram[s] as isize - 16;
+            let x = self.ram[s + 1] as isize - 8;
+            let mut tile = self.ram[s + 2];
+            let attributes = self.ram[s + 3];
+            let obp = (attributes >> 4) & 0b1;
+            let palette = if obp != 0 { self.ram[OBP1] } else { self.ram[OBP0] };
+
+            let mut row = ly as isize - y;
+            if attributes & 0b0100_0000 != 0 {
+                row = height - 1 - row;
+            }
+            if height == 16 {
+                tile &= 0xFE;
+            }
+            for col in 0..8 {
+                let px = x + col;
+                if px < 0 || px >= SCREEN_WIDTH as isize {
+                    continue;
+                }
+                let tx = if attributes & 0b0010_0000 != 0 { 7 - col } else { col };
+                let color = self.tile_pixel(false, tile, tx as usize, row as usize);
+                if color == 0 {
+                    continue;
+                }
+                if attributes & 0b1000_0000 != 0 && bg_colors[px as usize] != 0 {
+                    continue;
+                }
+                let shade = Memory::shade(palette, color);
+                self.gpu.framebuffer[ly * SCREEN_WIDTH + px as usize] = shade;
+                self.compat_color(ly * SCREEN_WIDTH + px as usize, Some(obp), shade);
+            }
+        }
+    }
+}
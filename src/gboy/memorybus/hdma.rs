@@ -0,0 +1,198 @@
+use super::memory::*;
+use super::super::state::{StateReader, StateWriter};
+use std::io;
+
+pub(super) const HDMA1: usize = 0xFF51;
+pub(super) const HDMA2: usize = 0xFF52;
+pub(super) const HDMA3: usize = 0xFF53;
+pub(super) const HDMA4: usize = 0xFF54;
+pub(super) const HDMA5: usize = 0xFF55;
+
+const BLOCK_SIZE: usize = 0x10;
+/// device clock cycles the cpu is held per block, twice as many cpu cycles in double speed
+const BLOCK_CYCLES: usize = 32;
+const HBLANK_MODE: u8 = 0b1000_0000;
+
+/// CGB vram DMA, source and destination move along as blocks are copied
+#[derive(Default)]
+pub struct Hdma {
+    source: usize,
+    destination: usize,
+    hblank: bool,
+    // cpu clock cycles owed to transfers, see Memory::take_dma_stall
+    stall: usize,
+}
+
+impl Hdma {
+    pub(super) fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.source);
+        w.usize(self.destination);
+        w.bool(self.hblank);
+        w.usize(self.stall);
+    }
+
+    pub(super) fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        self.source = r.usize()?;
+        self.destination = r.usize()?;
+        self.hblank = r.bool()?;
+        self.stall = r.usize()?;
+        Ok(())
+    }
+}
+
+impl Memory {
+    /// HDMA1-5 writes, HDMA1-4 are write only and keep reading 0xFF
+    pub(super) fn hdma_write(&mut self, address: usize, value: u8) {
+        match address {
+            HDMA1 => self.hdma.source = (self.hdma.source & 0x00F0) | (value as usize) << 8,
+            HDMA2 => self.hdma.source = (self.hdma.source & 0xFF00) | (value & 0xF0) as usize,
+            HDMA3 => self.hdma.destination = (self.hdma.destination & 0x00F0) | ((value & 0x1F) as usize) << 8,
+            HDMA4 => self.hdma.destination = (self.hdma.destination & 0x1F00) | (value & 0xF0) as usize,
+            HDMA5 => self.hdma_start(value),
+            _ => {},
+        }
+    }
+
+    fn hdma_start(&mut self, value: u8) {
+        let blocks = (value & 0x7F) as usize + 1;
+        if self.hdma.hblank && value & HBLANK_MODE == 0 {
+            // stops an HBlank transfer, bit 7 reads 1 with the blocks left
+            debug!("HBlank DMA cancelled, {} blocks left", (self.ram[HDMA5] & 0x7F) + 1);
+            self.hdma.hblank = false;
+            self.ram[HDMA5] |= HBLANK_MODE;
+            return;
+        }
+        self.ram[HDMA5] = value & 0x7F;
+        if value & HBLANK_MODE != 0 {
+            debug!("HBlank DMA {:#06x} => {:#06x}, {} blocks", self.hdma.source, 0x8000 + self.hdma.destination, blocks);
+            self.hdma.hblank = true;
+            // with the lcd off there is no HBlank to wait for
+            if !self.lcd_enabled() {
+                self.hdma_hblank();
+            }
+        } else {
+            debug!("general purpose DMA {:#06x} => {:#06x}, {} blocks", self.hdma.source, 0x8000 + self.hdma.destination, blocks);
+            for _ in 0..blocks {
+                self.hdma_block();
+            }
+            self.ram[HDMA5] = 0xFF;
+        }
+    }
+
+    /// copies one block on an HBlank transfer, called as the gpu enters HBlank
+    pub(super) fn hdma_hblank(&mut self) {
+        if !self.hdma.hblank {
+            return;
+        }
+        self.hdma_block();
+        if self.ram[HDMA5] & 0x7F == 0 {
+            self.hdma.hblank = false;
+            self.ram[HDMA5] = 0xFF;
+        } else {
+            self.ram[HDMA5] -= 1;
+        }
+    }
+
+    fn hdma_block(&mut self) {
+        for _ in 0..BLOCK_SIZE {
+            let value = self[self.hdma.source & 0xFFFF];
+            self.write(0x8000 + (self.hdma.destination & 0x1FFF), value);
+            self.hdma.source += 1;
+            self.hdma.destination += 1;
+        }
+        self.hdma.stall += if self.double_speed() { BLOCK_CYCLES * 2 } else { BLOCK_CYCLES };
+    }
+
+    /// cpu clock cycles the cpu must stay halted for because of vram DMA
+    pub fn take_dma_stall(&mut self) -> usize {
+        std::mem::replace(&mut self.hdma.stall, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::cgb::KEY1;
+    use super::super::super::cartridge;
+    use super::super::super::model::Model;
+
+    const LCDC: usize = 0xFF40;
+
+    /// CGB with 0xC000..=0xC0FF counting up, transfers set to copy it to 0x8010
+    fn memory() -> Memory {
+        let mut memory = new(cartridge::new(vec![0; 0x8000]).unwrap());
+        memory.set_model(Model::Cgb);
+        for i in 0..0x100_usize {
+            memory.write(0xC000 + i, i as u8);
+        }
+        memory.write(HDMA1, 0xC0);
+        memory.write(HDMA2, 0x00);
+        memory.write(HDMA3, 0x00);
+        memory.write(HDMA4, 0x10);
+        memory
+    }
+
+    fn copied(memory: &Memory, blocks: usize) -> bool {
+        (0..blocks * BLOCK_SIZE).all(|i| memory[0x8010 + i] == i as u8) && memory[0x8010 + blocks * BLOCK_SIZE] == 0x0
+    }
+
+    #[test]
+    fn test_general_purpose_dma() {
+        let mut memory = memory();
+        memory.write(HDMA5, 0x01);
+        assert!(copied(&memory, 2));
+        assert_eq!(memory[HDMA5], 0xFF);
+        assert_eq!(memory[HDMA1], 0xFF);
+        assert_eq!(memory.take_dma_stall(), 2 * BLOCK_CYCLES);
+        assert_eq!(memory.take_dma_stall(), 0);
+    }
+
+    #[test]
+    fn test_hblank_dma_and_cancel() {
+        let mut memory = memory();
+        memory.write(LCDC, 0x80);
+        memory.write(HDMA5, HBLANK_MODE | 0x02);
+        // active, blocks left minus one
+        assert_eq!(memory[HDMA5], 0x02);
+        assert!(copied(&memory, 0));
+
+        memory.hdma_hblank();
+        assert_eq!(memory[HDMA5], 0x01);
+        assert!(copied(&memory, 1));
+
+        memory.write(HDMA5, 0x00);
+        assert_eq!(memory[HDMA5], 0x81);
+        memory.hdma_hblank();
+        assert!(copied(&memory, 1));
+        assert_eq!(memory.take_dma_stall(), BLOCK_CYCLES);
+    }
+
+    #[test]
+    fn test_hblank_dma_runs_to_the_end() {
+        let mut lcd_on = memory();
+        lcd_on.write(LCDC, 0x80);
+        lcd_on.write(HDMA5, HBLANK_MODE | 0x01);
+        lcd_on.hdma_hblank();
+        lcd_on.hdma_hblank();
+        assert_eq!(lcd_on[HDMA5], 0xFF);
+        lcd_on.hdma_hblank();
+        assert!(copied(&lcd_on, 2));
+
+        // with the lcd off a block goes right away
+        let mut lcd_off = memory();
+        lcd_off.write(HDMA5, HBLANK_MODE | 0x01);
+        assert!(copied(&lcd_off, 1));
+        assert_eq!(lcd_off[HDMA5], 0x00);
+    }
+
+    #[test]
+    fn test_double_speed_stall() {
+        let mut memory = memory();
+        memory.write(KEY1, 0x01);
+        assert!(memory.switch_speed());
+        assert!(memory.double_speed());
+        memory.write(HDMA5, 0x00);
+        assert!(copied(&memory, 1));
+        assert_eq!(memory.take_dma_stall(), 2 * BLOCK_CYCLES);
+    }
+}
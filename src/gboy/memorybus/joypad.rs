@@ -0,0 +1,80 @@
+use super::memory::*;
+use super::super::state::{StateReader, StateWriter};
+use std::io;
+
+pub(super) const P1: usize = 0xFF00;
+const IF: usize = 0xFF0F;
+
+const JOYPAD_INTERRUPT: u8 = 0b0001_0000;
+const SELECT_DIRECTIONS: u8 = 0b0001_0000;
+const SELECT_BUTTONS: u8 = 0b0010_0000;
+
+/// pressed buttons as a bit set, directions on the low nibble
+pub const RIGHT: u8 = 0b0000_0001;
+pub const LEFT: u8 = 0b0000_0010;
+pub const UP: u8 = 0b0000_0100;
+pub const DOWN: u8 = 0b0000_1000;
+pub const A: u8 = 0b0001_0000;
+pub const B: u8 = 0b0010_0000;
+pub const SELECT: u8 = 0b0100_0000;
+pub const START: u8 = 0b1000_0000;
+
+#[derive(Default)]
+pub struct Joypad {
+    pressed: u8,
+    // P14/P15 as last written, 0 selects
+    select: u8,
+}
+
+impl Joypad {
+    pub(super) fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.pressed);
+        w.u8(self.select);
+    }
+
+    pub(super) fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        self.pressed = r.u8()?;
+        self.select = r.u8()?;
+        Ok(())
+    }
+}
+
+impl Memory {
+    pub(super) fn joypad_write(&mut self, value: u8) {
+        self.joypad.select = value & (SELECT_DIRECTIONS | SELECT_BUTTONS);
+        if self.model() == super::super::model::Model::Sgb {
+            self.sgb_joypad_write(self.joypad.select);
+        }
+        self.update_p1();
+    }
+
+    /// P1 as the cpu reads it, 0 bits for pressed buttons on the selected rows
+    pub(super) fn update_p1(&mut self) {
+        let select = self.joypad.select;
+        let mut lines = 0x0F;
+        if select & SELECT_DIRECTIONS == 0 {
+            lines &= !self.joypad.pressed & 0x0F;
+        }
+        if select & SELECT_BUTTONS == 0 {
+            lines &= !(self.joypad.pressed >> 4) & 0x0F;
+        }
+        if select == SELECT_DIRECTIONS | SELECT_BUTTONS {
+            // SGB multiplayer reports the current controller instead
+            lines = self.sgb_joypad_id();
+        }
+        self.ram[P1] = 0xC0 | select | lines;
+    }
+
+    pub fn buttons(&self) -> u8 {
+        self.joypad.pressed
+    }
+
+    /// new pressed buttons state, newly pressed ones raise the joypad interrupt
+    pub fn set_buttons(&mut self, pressed: u8) {
+        if pressed & !self.joypad.pressed != 0 {
+            self.ram[IF] |= JOYPAD_INTERRUPT;
+        }
+        self.joypad.pressed = pressed;
+        self.update_p1();
+    }
+}
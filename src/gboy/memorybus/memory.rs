@@ -1,84 +1,207 @@
-use std::ops::{Index, IndexMut};
+use std::cell::RefCell;
+use std::io;
+use std::ops::Index;
 
 use super::super::cartridge::*;
+use super::super::model::Model;
+use super::super::state::{StateReader, StateWriter};
+use super::cgb::*;
+use super::gpu::*;
+use super::hdma::*;
+use super::joypad::*;
+use super::sgb::*;
 const MEMORY_SIZE: usize = 0xFFFF;
 
+/// how addresses are decoded
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Bus {
+    /// cartridge, banked rams and io registers wired as on the console
+    Console,
+    /// plain 64KiB of ram, lets the cpu be exercised alone against test vectors
+    #[cfg_attr(not(test), allow(dead_code))]
+    Flat,
+}
+
 pub struct Memory {
-    ram: [u8; MEMORY_SIZE],
+    pub(super) ram: [u8; MEMORY_SIZE],
+    pub(super) vram: Vec<u8>,
+    wram: Vec<u8>,
     cartridge: Cartridge,
+    bootrom: Vec<u8>,
+    model: Model,
+    pub(super) gpu: Gpu,
+    pub(super) hdma: Hdma,
+    pub(super) joypad: Joypad,
+    pub(super) sgb: Sgb,
 
     // Internals
     ime: u8,
+    bus: Bus,
+    // flat bus reads and writes in order, (address, value, written)
+    accesses: RefCell<Vec<(usize, u8, bool)>>,
 }
 
 pub fn new(cartridge: Cartridge) -> Memory {
-    Memory {
+    let mut memory = Memory {
         ram: [0; MEMORY_SIZE],
+        vram: vec![0; 2 * VRAM_BANK_SIZE],
+        wram: vec![0; 8 * WRAM_BANK_SIZE],
         cartridge: cartridge,
+        bootrom: Vec::new(),
+        model: Model::default(),
+        gpu: Gpu::default(),
+        hdma: Hdma::default(),
+        joypad: Joypad::default(),
+        sgb: Sgb::default(),
 
         // internals
         ime: 0x0,
-    }
+        bus: Bus::Console,
+        accesses: RefCell::new(Vec::new()),
+    };
+    memory.reset_cgb_registers();
+    memory.update_p1();
+    memory
 }
 
 impl<T> Index<T> for Memory where T: Into<usize>{
     type Output = u8;
     fn index(&self, i: T) -> &Self::Output {
-        //&self.ram[i.into()]
-        let idx = &i.into();
-        match *idx {
+        let idx = i.into();
+        match idx {
+            _ if self.bus == Bus::Flat => {
+                let value = if idx == 0xFFFF { &self.ime } else { &self.ram[idx] };
+                self.accesses.borrow_mut().push((idx, *value, false));
+                value
+            },
             0xFFFF => &self.ime,
-            _ => &self.ram[*idx as usize],
+            0x0000..=0x00FF if self.bootrom_mapped() => &self.bootrom[idx],
+            // CGB boot roms leave a hole for the cartridge header
+            0x0200..=0x08FF if self.bootrom_mapped() && self.bootrom.len() > idx => &self.bootrom[idx],
+            0x0000..=0x7FFF => self.cartridge.read_rom(idx),
+            0x8000..=0x9FFF => &self.vram[self.vram_offset(idx)],
+            0xA000..=0xBFFF => self.cartridge.read_ram(idx),
+            0xC000..=0xFDFF => &self.wram[self.wram_offset(idx)],
+            _ => &self.ram[idx],
         }
     }
 }
 
-impl<T> IndexMut<T> for Memory where T: Into<usize>{
-    fn index_mut(&mut self, i: T) -> &mut Self::Output {
-        let idx = &i.into();
-        match *idx {
-            0xFFFF => &mut self.ime,
-            //0xFF0F => panic!(""),
-            _ => &mut self.ram[*idx as usize],
-        }        
-    }
-}
-
 impl Memory {
     pub fn memory_size(&mut self) -> usize {
         MEMORY_SIZE
     }
 
-    pub fn load_cartridge_header(&mut self) {
-        let s = self.cartridge.header_start();
-        let e = self.cartridge.header_end();
-        for i in s..e {
-            self.ram[i] = self.cartridge.read(i);
+    pub fn write<T: Into<usize>>(&mut self, address: T, value: u8) {
+        let idx = address.into();
+        match idx {
+            _ if self.bus == Bus::Flat => {
+                self.accesses.borrow_mut().push((idx, value, true));
+                if idx == 0xFFFF { self.ime = value } else { self.ram[idx] = value }
+            },
+            0xFFFF => self.ime = value,
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.write(idx, value),
+            0x8000..=0x9FFF => {
+                let offset = self.vram_offset(idx);
+                self.vram[offset] = value;
+            },
+            0xC000..=0xFDFF => {
+                let offset = self.wram_offset(idx);
+                self.wram[offset] = value;
+            },
+            P1 => self.joypad_write(value),
+            _ if self.cgb_write(idx, value) => {},
+            _ => self.ram[idx] = value,
         }
     }
 
-    pub fn load_cartridge_rom(&mut self) {
-        let s = self.cartridge.header_start();
-        let e = self.cartridge.rom_len();
-        for i in s..e {
-            self.ram[i] = self.cartridge.read(i);
-        }
+    #[cfg(test)]
+    pub(crate) fn set_bus(&mut self, bus: Bus) {
+        self.bus = bus;
+    }
+
+    /// flat bus accesses since the last call, (address, value, written)
+    #[cfg(test)]
+    pub(crate) fn take_accesses(&mut self) -> Vec<(usize, u8, bool)> {
+        self.accesses.get_mut().split_off(0)
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+        self.reset_cgb_registers();
+    }
+
+    /// boot rom overlays 0x0000..=0x00FF (and 0x0200..=0x08FF on CGB) until something is written on 0xFF50
+    pub fn load_bootrom(&mut self, bootrom: Vec<u8>) {
+        self.bootrom = bootrom;
     }
 
-    pub fn cartridge_title(&mut self) -> String {
-        self.cartridge.title()
+    fn bootrom_mapped(&self) -> bool {
+        self.ram[0xFF50] == 0x0 && !self.bootrom.is_empty()
     }
-    
-    pub fn destination_code(&mut self) -> String {
-        self.cartridge.destination_code()
+
+    /// walks cartridge chips, like the MBC3 clock, up to the cpu clock_cycles count
+    pub fn cartridge_tick(&mut self, clock_cycles: usize) {
+        self.cartridge.tick(clock_cycles);
+    }
+
+    pub fn has_battery(&mut self) -> bool {
+        self.cartridge.has_battery()
+    }
+
+    pub fn battery_save(&mut self) -> Vec<u8> {
+        self.cartridge.battery_save()
+    }
+
+    pub fn battery_contents(&mut self) -> Vec<u8> {
+        self.cartridge.battery_contents()
+    }
+
+    pub fn load_battery_save(&mut self, save: &[u8]) -> Result<(), io::Error> {
+        self.cartridge.load_battery_save(save)
+    }
+
+    /// whole bus state, cartridge included, model is left to the state header
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        w.bytes(&self.vram);
+        w.bytes(&self.wram);
+        w.bytes(&self.bootrom);
+        w.u8(self.ime);
+        self.cartridge.save_state(w);
+        self.gpu.save_state(w);
+        self.hdma.save_state(w);
+        self.joypad.save_state(w);
+        self.sgb.save_state(w);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        r.bytes_into(&mut self.ram)?;
+        r.bytes_into(&mut self.vram)?;
+        r.bytes_into(&mut self.wram)?;
+        self.bootrom = r.bytes()?.to_vec();
+        self.ime = r.u8()?;
+        self.cartridge.load_state(r)?;
+        self.gpu.load_state(r)?;
+        self.hdma.load_state(r)?;
+        self.joypad.load_state(r)?;
+        self.sgb.load_state(r)
+    }
+
+    pub fn cartridge_sha1(&mut self) -> String {
+        self.cartridge.sha1()
     }
 
-    pub fn licensee_code(&mut self) -> String {
-        self.cartridge.licensee_code()
+    pub fn cartridge_header(&self) -> &Header {
+        self.cartridge.header()
     }
 
-    pub fn cartridge_type(&mut self) -> String {
-        self.cartridge.cart_type()
+    pub fn validate_cartridge(&mut self) -> Validation {
+        self.cartridge.validate()
     }
 
     pub fn cartridge_header_checksum(&mut self) -> (u8, u8) {
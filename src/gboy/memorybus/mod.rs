@@ -1,6 +1,11 @@
-mod gpu;
-mod joypad;
+mod cgb;
+mod color;
+pub mod gpu;
+pub use gpu::*;
+mod hdma;
+pub mod joypad;
 pub mod memory;
 pub use memory::*;
+pub mod sgb;
 mod sound;
 
@@ -0,0 +1,613 @@
+use super::gpu::*;
+use super::memory::*;
+use super::super::state::{StateReader, StateWriter};
+use std::io;
+
+/// SGB output, the game screen sits in the middle of the border
+pub const SGB_WIDTH: usize = 256;
+pub const SGB_HEIGHT: usize = 224;
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+
+const LCDC: usize = 0xFF40;
+
+const PACKET_SIZE: usize = 16;
+const PACKET_BITS: usize = PACKET_SIZE * 8;
+const TRANSFER_SIZE: usize = 0x1000;
+const ATTRIBUTE_COLUMNS: usize = SCREEN_WIDTH / 8;
+const ATTRIBUTE_ROWS: usize = SCREEN_HEIGHT / 8;
+const ATTRIBUTE_FILE_SIZE: usize = ATTRIBUTE_COLUMNS * ATTRIBUTE_ROWS / 4;
+const ATTRIBUTE_FILES: usize = 45;
+
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const ATTR_TRN: u8 = 0x15;
+const ATTR_SET: u8 = 0x16;
+const MASK_EN: u8 = 0x17;
+
+/// white to black, as RGB555, until the game sends its own palettes
+const GREYS: [u16; 4] = [0x7FFF, 0x5294, 0x294A, 0x0000];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Mask {
+    Cancel,
+    Freeze,
+    Black,
+    Color0,
+}
+
+/// SGB side of the machine, fed with packets pulsed on P14/P15
+pub struct Sgb {
+    // packet reception
+    receiving: bool,
+    bits: usize,
+    packet: [u8; PACKET_SIZE],
+    packets: Vec<u8>,
+    last_select: u8,
+    // multiplayer, MLT_REQ
+    players: u8,
+    player: u8,
+
+    palettes: [[u16; 4]; 4],
+    system_palettes: Vec<u16>,
+    attributes: [u8; ATTRIBUTE_COLUMNS * ATTRIBUTE_ROWS],
+    attribute_files: Vec<u8>,
+    mask: Mask,
+    frozen: Vec<u8>,
+
+    // border, SNES 4bpp tiles, 32x28 map and palettes 4 to 7
+    border_tiles: Vec<u8>,
+    border_map: Vec<u8>,
+    border_palettes: [u16; 64],
+}
+
+impl Default for Sgb {
+    fn default() -> Sgb {
+        Sgb {
+            receiving: false,
+            bits: 0,
+            packet: [0; PACKET_SIZE],
+            packets: Vec::new(),
+            last_select: 0x30,
+            players: 1,
+            player: 0,
+            palettes: [GREYS; 4],
+            system_palettes: vec![0; 512 * 4],
+            attributes: [0; ATTRIBUTE_COLUMNS * ATTRIBUTE_ROWS],
+            attribute_files: vec![0; ATTRIBUTE_FILES * ATTRIBUTE_FILE_SIZE],
+            mask: Mask::Cancel,
+            frozen: Vec::new(),
+            border_tiles: vec![0; 256 * 32],
+            border_map: vec![0; 0x800],
+            border_palettes: [0; 64],
+        }
+    }
+}
+
+impl Sgb {
+    pub(super) fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.receiving);
+        w.usize(self.bits);
+        w.bytes(&self.packet);
+        w.bytes(&self.packets);
+        w.u8(self.last_select);
+        w.u8(self.players);
+        w.u8(self.player);
+        for palette in self.palettes.iter() {
+            w.u16s(palette);
+        }
+        w.u16s(&self.system_palettes);
+        w.bytes(&self.attributes);
+        w.bytes(&self.attribute_files);
+        w.u8(self.mask as u8);
+        w.bytes(&self.frozen);
+        w.bytes(&self.border_tiles);
+        w.bytes(&self.border_map);
+        w.u16s(&self.border_palettes);
+    }
+
+    pub(super) fn load_state(&mut self, r: &mut StateReader) -> Result<(), io::Error> {
+        self.receiving = r.bool()?;
+        self.bits = r.usize()?;
+        r.bytes_into(&mut self.packet)?;
+        self.packets = r.bytes()?.to_vec();
+        self.last_select = r.u8()?;
+        self.players = r.u8()?;
+        self.player = r.u8()?;
+        for palette in self.palettes.iter_mut() {
+            r.u16s_into(palette)?;
+        }
+        r.u16s_into(&mut self.system_palettes)?;
+        r.bytes_into(&mut self.attributes)?;
+        r.bytes_into(&mut self.attribute_files)?;
+        self.mask = match r.u8()? {
+            1 => Mask::Freeze,
+            2 => Mask::Black,
+            3 => Mask::Color0,
+            _ => Mask::Cancel,
+        };
+        self.frozen = r.bytes()?.to_vec();
+        r.bytes_into(&mut self.border_tiles)?;
+        r.bytes_into(&mut self.border_map)?;
+        r.u16s_into(&mut self.border_palettes)
+    }
+}
+
+fn color(data: &[u8], at: usize) -> u16 {
+    (data[at] as u16 | (data[at + 1] as u16) << 8) & 0x7FFF
+}
+
+impl Memory {
+    /// P14/P15 writes: both low resets, P14 low sends a 0, P15 low a 1
+    pub(super) fn sgb_joypad_write(&mut self, select: u8) {
+        let last = self.sgb.last_select;
+        self.sgb.last_select = select;
+        if select == last {
+            return;
+        }
+        match select {
+            0x00 => {
+                self.sgb.receiving = true;
+                self.sgb.bits = 0;
+                self.sgb.packet = [0; PACKET_SIZE];
+            },
+            0x10 | 0x20 if self.sgb.receiving && last == 0x30 => {
+                let bit = (select == 0x10) as u8;
+                if self.sgb.bits == PACKET_BITS {
+                    // stop bit, always a 0
+                    self.sgb.receiving = false;
+                    if bit == 0 {
+                        self.sgb_packet();
+                    } else {
+                        debug!("SGB packet without stop bit dropped");
+                    }
+                    return;
+                }
+                self.sgb.packet[self.sgb.bits / 8] |= bit << (self.sgb.bits % 8);
+                self.sgb.bits += 1;
+            },
+            0x30 if !self.sgb.receiving && last & 0x20 == 0 && self.sgb.players > 1 => {
+                self.sgb.player = (self.sgb.player + 1) % self.sgb.players;
+            },
+            _ => {},
+        }
+    }
+
+    /// P1 low nibble with both rows deselected
+    pub(super) fn sgb_joypad_id(&self) -> u8 {
+        0x0F - self.sgb.player
+    }
+
+    fn sgb_packet(&mut self) {
+        let packet = self.sgb.packet;
+        self.sgb.packets.extend_from_slice(&packet);
+        let length = match self.sgb.packets[0] & 0b111 {
+            0 => 1,
+            l => l as usize,
+        };
+        if self.sgb.packets.len() < length * PACKET_SIZE {
+            return;
+        }
+        let data = std::mem::take(&mut self.sgb.packets);
+        // the SGB bios only listens to games declaring support
+        if !self.cartridge_header().sgb {
+            debug!("SGB command {:#04x} ignored, cartridge without SGB support", data[0] >> 3);
+            return;
+        }
+        self.sgb_command(&data);
+    }
+
+    fn sgb_command(&mut self, data: &[u8]) {
+        let command = data[0] >> 3;
+        trace!("SGB command {:#04x} => {:?}", command, data);
+        match command {
+            PAL01 => self.sgb_palettes(data, 0, 1),
+            PAL23 => self.sgb_palettes(data, 2, 3),
+            PAL03 => self.sgb_palettes(data, 0, 3),
+            PAL12 => self.sgb_palettes(data, 1, 2),
+            ATTR_BLK => self.sgb_attr_blk(data),
+            ATTR_LIN => self.sgb_attr_lin(data),
+            ATTR_DIV => self.sgb_attr_div(data),
+            ATTR_CHR => self.sgb_attr_chr(data),
+            PAL_SET => {
+                for p in 0..4 {
+                    let id = (color(data, 1 + p * 2) & 0x1FF) as usize;
+                    let mut palette = [0; 4];
+                    palette.copy_from_slice(&self.sgb.system_palettes[id * 4..id * 4 + 4]);
+                    self.sgb.palettes[p] = palette;
+                }
+                // color 0 is shared, the first palette one wins
+                for p in 1..4 {
+                    self.sgb.palettes[p][0] = self.sgb.palettes[0][0];
+                }
+                if data[9] & 0b1000_0000 != 0 {
+                    self.sgb_attribute_file(data[9] & 0x3F);
+                }
+                if data[9] & 0b0100_0000 != 0 {
+                    self.sgb.mask = Mask::Cancel;
+                }
+            },
+            PAL_TRN => {
+                let transfer = self.sgb_transfer();
+                for (i, c) in self.sgb.system_palettes.iter_mut().enumerate() {
+                    *c = color(&transfer, i * 2);
+                }
+            },
+            MLT_REQ => {
+                self.sgb.players = match data[1] & 0b11 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.sgb.player = 0;
+                debug!("SGB {} players", self.sgb.players);
+            },
+            CHR_TRN => {
+                let transfer = self.sgb_transfer();
+                let offset = (data[1] & 0b1) as usize * TRANSFER_SIZE;
+                self.sgb.border_tiles[offset..offset + TRANSFER_SIZE].copy_from_slice(&transfer);
+            },
+            PCT_TRN => {
+                let transfer = self.sgb_transfer();
+                self.sgb.border_map.copy_from_slice(&transfer[..0x800]);
+                for (i, c) in self.sgb.border_palettes.iter_mut().enumerate() {
+                    *c = color(&transfer, 0x800 + i * 2);
+                }
+            },
+            ATTR_TRN => {
+                let transfer = self.sgb_transfer();
+                let size = self.sgb.attribute_files.len();
+                self.sgb.attribute_files.copy_from_slice(&transfer[..size]);
+            },
+            ATTR_SET => {
+                self.sgb_attribute_file(data[1] & 0x3F);
+                if data[1] & 0b0100_0000 != 0 {
+                    self.sgb.mask = Mask::Cancel;
+                }
+            },
+            MASK_EN => {
+                self.sgb.mask = match data[1] & 0b11 {
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    3 => Mask::Color0,
+                    _ => Mask::Cancel,
+                };
+                if self.sgb.mask == Mask::Freeze {
+                    self.sgb.frozen = self.gpu.framebuffer.clone();
+                }
+                debug!("SGB mask {:?}", self.sgb.mask);
+            },
+            _ => debug!("SGB command {:#04x} not supported", command),
+        }
+    }
+
+    /// PAL01, PAL23, PAL03 and PAL12, color 0 is shared by all palettes
+    fn sgb_palettes(&mut self, data: &[u8], first: usize, second: usize) {
+        let color0 = color(data, 1);
+        for palette in self.sgb.palettes.iter_mut() {
+            palette[0] = color0;
+        }
+        for c in 1..4 {
+            self.sgb.palettes[first][c] = color(data, 1 + c * 2);
+            self.sgb.palettes[second][c] = color(data, 7 + c * 2);
+        }
+    }
+
+    fn sgb_attr_blk(&mut self, data: &[u8]) {
+        let sets = (data[1] & 0x1F) as usize;
+        for set in data[2..].chunks(6).take(sets) {
+            if set.len() < 6 {
+                break;
+            }
+            let control = set[0];
+            let inside = set[1] & 0b11;
+            let border = (set[1] >> 2) & 0b11;
+            let outside = (set[1] >> 4) & 0b11;
+            // a lone inside or outside change takes the border along
+            let border = match control & 0b111 {
+                0b001 => Some(inside),
+                0b100 => Some(outside),
+                c if c & 0b010 != 0 => Some(border),
+                _ => None,
+            };
+            let (x1, y1, x2, y2) = (set[2] as usize, set[3] as usize, set[4] as usize, set[5] as usize);
+            for y in 0..ATTRIBUTE_ROWS {
+                for x in 0..ATTRIBUTE_COLUMNS {
+                    let within = x >= x1 && x <= x2 && y >= y1 && y <= y2;
+                    let edge = within && (x == x1 || x == x2 || y == y1 || y == y2);
+                    let palette = if edge {
+                        border
+                    } else if within && control & 0b001 != 0 {
+                        Some(inside)
+                    } else if !within && control & 0b100 != 0 {
+                        Some(outside)
+                    } else {
+                        None
+                    };
+                    if let Some(p) = palette {
+                        self.sgb.attributes[y * ATTRIBUTE_COLUMNS + x] = p;
+                    }
+                }
+            }
+        }
+    }
+
+    fn sgb_attr_lin(&mut self, data: &[u8]) {
+        let lines = data[1] as usize;
+        for line in data[2..].iter().take(lines) {
+            let at = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 0b11;
+            if line & 0b1000_0000 != 0 {
+                if at < ATTRIBUTE_ROWS {
+                    for x in 0..ATTRIBUTE_COLUMNS {
+                        self.sgb.attributes[at * ATTRIBUTE_COLUMNS + x] = palette;
+                    }
+                }
+            } else if at < ATTRIBUTE_COLUMNS {
+                for y in 0..ATTRIBUTE_ROWS {
+                    self.sgb.attributes[y * ATTRIBUTE_COLUMNS + at] = palette;
+                }
+            }
+        }
+    }
+
+    fn sgb_attr_div(&mut self, data: &[u8]) {
+        let after = data[1] & 0b11;
+        let before = (data[1] >> 2) & 0b11;
+        let on = (data[1] >> 4) & 0b11;
+        let horizontal = data[1] & 0b0100_0000 != 0;
+        let at = data[2] as usize;
+        for y in 0..ATTRIBUTE_ROWS {
+            for x in 0..ATTRIBUTE_COLUMNS {
+                let position = if horizontal { y } else { x };
+                self.sgb.attributes[y * ATTRIBUTE_COLUMNS + x] = if position < at {
+                    before
+                } else if position == at {
+                    on
+                } else {
+                    after
+                };
+            }
+        }
+    }
+
+    fn sgb_attr_chr(&mut self, data: &[u8]) {
+        let mut x = data[1] as usize;
+        let mut y = data[2] as usize;
+        let count = color(data, 3) as usize;
+        let vertical = data[5] & 0b1 != 0;
+        for i in 0..count {
+            let byte = match data.get(6 + i / 4) {
+                Some(b) => *b,
+                None => break,
+            };
+            if x >= ATTRIBUTE_COLUMNS || y >= ATTRIBUTE_ROWS {
+                break;
+            }
+            self.sgb.attributes[y * ATTRIBUTE_COLUMNS + x] = (byte >> (6 - (i % 4) * 2)) & 0b11;
+            if vertical {
+                y += 1;
+                if y == ATTRIBUTE_ROWS {
+                    y = 0;
+                    x += 1;
+                }
+            } else {
+                x += 1;
+                if x == ATTRIBUTE_COLUMNS {
+                    x = 0;
+                    y += 1;
+                }
+            }
+        }
+    }
+
+    fn sgb_attribute_file(&mut self, file: u8) {
+        let file = file as usize;
+        if file >= ATTRIBUTE_FILES {
+            debug!("SGB attribute file {} out of range", file);
+            return;
+        }
+        let data = &self.sgb.attribute_files[file * ATTRIBUTE_FILE_SIZE..(file + 1) * ATTRIBUTE_FILE_SIZE];
+        for (i, cell) in self.sgb.attributes.iter_mut().enumerate() {
+            *cell = (data[i / 4] >> (6 - (i % 4) * 2)) & 0b11;
+        }
+    }
+
+    /// 4KiB the game puts on screen for *_TRN commands, read as the tiles
+    /// the background map shows, row by row
+    fn sgb_transfer(&self) -> Vec<u8> {
+        let lcdc = self.ram[LCDC];
+        let map = if lcdc & 0b0000_1000 != 0 { 0x9C00 } else { 0x9800 };
+        let signed = lcdc & 0b0001_0000 == 0;
+        let mut transfer = Vec::with_capacity(TRANSFER_SIZE);
+        for i in 0..TRANSFER_SIZE / 16 {
+            let tile = self.vram(0, map + (i / ATTRIBUTE_COLUMNS) * 32 + i % ATTRIBUTE_COLUMNS);
+            let base = if signed {
+                (0x9000 + (tile as i8 as isize) * 16) as usize
+            } else {
+                0x8000 + tile as usize * 16
+            };
+            for b in 0..16 {
+                transfer.push(self.vram(0, base + b));
+            }
+        }
+        transfer
+    }
+
+    /// game screen through the SGB palettes and mask, RGB555
+    pub fn sgb_screen(&self) -> Vec<u16> {
+        let shades = match self.sgb.mask {
+            Mask::Freeze => &self.sgb.frozen,
+            _ => &self.gpu.framebuffer,
+        };
+        let mut screen = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT);
+        for (i, shade) in shades.iter().enumerate() {
+            let (x, y) = (i % SCREEN_WIDTH, i / SCREEN_WIDTH);
+            screen.push(match self.sgb.mask {
+                Mask::Black => 0x0000,
+                Mask::Color0 => self.sgb.palettes[0][0],
+                _ => {
+                    let palette = self.sgb.attributes[(y / 8) * ATTRIBUTE_COLUMNS + x / 8];
+                    self.sgb.palettes[palette as usize][(*shade & 0b11) as usize]
+                },
+            });
+        }
+        screen
+    }
+
+    /// whole SGB picture, SGB_WIDTH x SGB_HEIGHT RGB555 with the border over the game screen
+    pub fn sgb_frame(&self) -> Vec<u16> {
+        let mut frame = vec![self.sgb.palettes[0][0]; SGB_WIDTH * SGB_HEIGHT];
+        for (i, c) in self.sgb_screen().iter().enumerate() {
+            let (x, y) = (i % SCREEN_WIDTH, i / SCREEN_WIDTH);
+            frame[(y + SCREEN_Y) * SGB_WIDTH + x + SCREEN_X] = *c;
+        }
+        for row in 0..SGB_HEIGHT / 8 {
+            for column in 0..SGB_WIDTH / 8 {
+                let at = (row * 32 + column) * 2;
+                let entry = self.sgb.border_map[at] as u16 | (self.sgb.border_map[at + 1] as u16) << 8;
+                let tile = &self.sgb.border_tiles[(entry & 0xFF) as usize * 32..][..32];
+                let palette = (((entry >> 10) & 0b111) as usize).saturating_sub(4);
+                for ty in 0..8 {
+                    for tx in 0..8 {
+                        let px = if entry & 0x4000 != 0 { 7 - tx } else { tx };
+                        let py = if entry & 0x8000 != 0 { 7 - ty } else { ty };
+                        let bit = 7 - px;
+                        let index = ((tile[py * 2] >> bit) & 1)
+                            | ((tile[py * 2 + 1] >> bit) & 1) << 1
+                            | ((tile[16 + py * 2] >> bit) & 1) << 2
+                            | ((tile[16 + py * 2 + 1] >> bit) & 1) << 3;
+                        // color 0 is transparent
+                        if index == 0 {
+                            continue;
+                        }
+                        let (x, y) = (column * 8 + tx, row * 8 + ty);
+                        frame[y * SGB_WIDTH + x] = self.sgb.border_palettes[(palette % 4) * 16 + index as usize];
+                    }
+                }
+            }
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::joypad::P1;
+    use super::super::super::cartridge;
+    use super::super::super::model::Model;
+
+    fn memory(sgb: bool) -> Memory {
+        let mut rom = vec![0; 0x8000];
+        if sgb {
+            rom[0x146] = 0x03;
+            rom[0x14B] = 0x33;
+        }
+        let mut memory = new(cartridge::new(rom).unwrap());
+        memory.set_model(Model::Sgb);
+        memory
+    }
+
+    /// pulses a packet on P14/P15, least significant bit first, then the stop bit
+    fn send(memory: &mut Memory, packet: &[u8], stop: u8) {
+        memory.write(P1, 0x00);
+        memory.write(P1, 0x30);
+        let bits = (0..PACKET_BITS).map(|i| packet.get(i / 8).map_or(0, |b| (b >> (i % 8)) & 1));
+        for bit in bits.chain(Some(stop)) {
+            memory.write(P1, if bit == 1 { 0x10 } else { 0x20 });
+            memory.write(P1, 0x30);
+        }
+    }
+
+    fn pal01() -> Vec<u8> {
+        let colors: [u16; 7] = [0x001F, 0x03E0, 0x7C00, 0x1234, 0x0421, 0x0842, 0x1084];
+        let mut packet = vec![PAL01 << 3 | 1];
+        for c in colors.iter() {
+            packet.extend_from_slice(&c.to_le_bytes());
+        }
+        packet
+    }
+
+    #[test]
+    fn test_packet_reception() {
+        let mut memory = memory(true);
+        send(&mut memory, &pal01(), 0);
+        assert_eq!(memory.sgb.palettes[0], [0x001F, 0x03E0, 0x7C00, 0x1234]);
+
+        // a 1 for stop bit drops the packet
+        let mut dropped = self::memory(true);
+        send(&mut dropped, &pal01(), 1);
+        assert_eq!(dropped.sgb.palettes[0], GREYS);
+
+        // the bios ignores games without SGB support
+        let mut unsupported = self::memory(false);
+        send(&mut unsupported, &pal01(), 0);
+        assert_eq!(unsupported.sgb.palettes[0], GREYS);
+    }
+
+    #[test]
+    fn test_pal01_shares_color_0() {
+        let mut memory = memory(true);
+        send(&mut memory, &pal01(), 0);
+        assert_eq!(memory.sgb.palettes[1], [0x001F, 0x0421, 0x0842, 0x1084]);
+        assert_eq!(memory.sgb.palettes[2], [0x001F, GREYS[1], GREYS[2], GREYS[3]]);
+        assert_eq!(memory.sgb_screen()[0], 0x001F);
+    }
+
+    #[test]
+    fn test_attr_blk() {
+        let mut memory = memory(true);
+        // inside 1 with a border of 2 on columns and rows 1..=3, then outside 3 around 10..=12
+        let packet = [ATTR_BLK << 3 | 1, 2, 0b011, 0b1001, 1, 1, 3, 3, 0b100, 0b11_0000, 10, 10, 12, 12];
+        send(&mut memory, &packet, 0);
+        let attribute = |x: usize, y: usize| memory.sgb.attributes[y * ATTRIBUTE_COLUMNS + x];
+        assert_eq!(attribute(2, 2), 3);
+        assert_eq!(attribute(1, 1), 3);
+        assert_eq!(attribute(11, 11), 0);
+        // the lone outside change takes the border along
+        assert_eq!(attribute(10, 12), 3);
+
+        let mut memory = self::memory(true);
+        send(&mut memory, &packet[..8], 0);
+        let attribute = |x: usize, y: usize| memory.sgb.attributes[y * ATTRIBUTE_COLUMNS + x];
+        assert_eq!(attribute(2, 2), 1);
+        assert_eq!(attribute(1, 2), 2);
+        assert_eq!(attribute(3, 3), 2);
+        assert_eq!(attribute(0, 0), 0);
+        assert_eq!(attribute(4, 2), 0);
+    }
+
+    #[test]
+    fn test_mlt_req_cycles_players() {
+        let mut memory = memory(true);
+        memory.write(P1, 0x30);
+        assert_eq!(memory[P1] & 0x0F, 0x0F);
+        send(&mut memory, &[MLT_REQ << 3 | 1, 0x01], 0);
+        assert_eq!(memory.sgb.players, 2);
+
+        // deselecting buttons moves to the next controller
+        memory.write(P1, 0x10);
+        memory.write(P1, 0x30);
+        assert_eq!(memory[P1] & 0x0F, 0x0E);
+        memory.write(P1, 0x10);
+        memory.write(P1, 0x30);
+        assert_eq!(memory[P1] & 0x0F, 0x0F);
+
+        send(&mut memory, &[MLT_REQ << 3 | 1, 0x00], 0);
+        memory.write(P1, 0x10);
+        memory.write(P1, 0x30);
+        assert_eq!(memory[P1] & 0x0F, 0x0F);
+    }
+}
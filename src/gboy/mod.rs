@@ -5,6 +5,15 @@ mod util;
 use util::*;
 
 pub mod debugger;
+pub mod link;
+pub mod model;
+pub mod movie;
+pub mod patch;
+pub mod rewind;
+pub mod rom;
+pub mod screen;
+pub mod state;
+pub mod timing;
 use debugger::*;
 
 pub mod cpu;
@@ -0,0 +1,118 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::cartridge::{CgbFlag, Header};
+
+/// emulated hardware
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Model {
+    #[default]
+    Dmg,
+    // CGB running a cartridge without CGB support, with DMG palettes
+    CgbCompat,
+    Cgb,
+    Sgb,
+}
+
+impl FromStr for Model {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Model, String> {
+        match s.to_lowercase().as_str() {
+            "dmg" => Ok(Model::Dmg),
+            "cgb" => Ok(Model::Cgb),
+            "sgb" => Ok(Model::Sgb),
+            _ => Err(format!("unknown model {}, expected dmg, cgb or sgb", s)),
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Model::Dmg => "DMG",
+            Model::CgbCompat => "CGB (DMG compatibility)",
+            Model::Cgb => "CGB",
+            Model::Sgb => "SGB",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// model to emulate, from the header flags unless one is requested. CGB
+/// running a cartridge without CGB support ends up in compatibility mode
+pub fn select(header: &Header, requested: Option<Model>) -> Model {
+    let model = match requested {
+        Some(Model::Cgb) | Some(Model::CgbCompat) if header.cgb == CgbFlag::Dmg => Model::CgbCompat,
+        Some(m) => m,
+        None if header.cgb != CgbFlag::Dmg => Model::Cgb,
+        None if header.sgb => Model::Sgb,
+        None => Model::Dmg,
+    };
+    if header.cgb == CgbFlag::Only && model != Model::Cgb {
+        warn!("cartridge requires a CGB, running on {}", model);
+    }
+    model
+}
+
+impl Model {
+    pub fn is_cgb(&self) -> bool {
+        *self == Model::Cgb || *self == Model::CgbCompat
+    }
+
+    /// boot rom file name looked up on the boot rom directory
+    pub fn bootrom_name(&self) -> &'static str {
+        match self {
+            Model::Dmg => "dmg_boot.bin",
+            Model::CgbCompat | Model::Cgb => "cgb_boot.bin",
+            Model::Sgb => "sgb_boot.bin",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::cartridge::header;
+
+    fn header(cgb: u8, sgb: u8, licensee: u8) -> Header {
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = cgb;
+        rom[0x146] = sgb;
+        rom[0x14B] = licensee;
+        header::parse(&rom).unwrap()
+    }
+
+    #[test]
+    fn test_select_from_header() {
+        assert_eq!(select(&header(0x80, 0x00, 0x00), None), Model::Cgb);
+        assert_eq!(select(&header(0xC0, 0x00, 0x00), None), Model::Cgb);
+        assert_eq!(select(&header(0x00, 0x03, 0x33), None), Model::Sgb);
+        // the SGB flag only counts with the new licensee code
+        assert_eq!(select(&header(0x00, 0x03, 0x01), None), Model::Dmg);
+        assert_eq!(select(&header(0x00, 0x00, 0x33), None), Model::Dmg);
+        // CGB support wins over SGB support
+        assert_eq!(select(&header(0x80, 0x03, 0x33), None), Model::Cgb);
+    }
+
+    #[test]
+    fn test_select_requested() {
+        let dmg = header(0x00, 0x03, 0x33);
+        let cgb_only = header(0xC0, 0x00, 0x00);
+        assert_eq!(select(&cgb_only, Some(Model::Dmg)), Model::Dmg);
+        assert_eq!(select(&cgb_only, Some(Model::Sgb)), Model::Sgb);
+        assert_eq!(select(&dmg, Some(Model::Dmg)), Model::Dmg);
+        // CGB on a DMG cartridge runs it in compatibility mode
+        assert_eq!(select(&dmg, Some(Model::Cgb)), Model::CgbCompat);
+        assert_eq!(select(&header(0x80, 0x00, 0x00), Some(Model::Cgb)), Model::Cgb);
+        assert_eq!("CGB".parse(), Ok(Model::Cgb));
+        assert!("gba".parse::<Model>().is_err());
+    }
+
+    #[test]
+    fn test_bootrom_name() {
+        assert_eq!(Model::Dmg.bootrom_name(), "dmg_boot.bin");
+        assert_eq!(Model::Sgb.bootrom_name(), "sgb_boot.bin");
+        assert_eq!(Model::Cgb.bootrom_name(), "cgb_boot.bin");
+        assert_eq!(Model::CgbCompat.bootrom_name(), "cgb_boot.bin");
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::io::{self, Read};
+
+use super::super::memorybus::joypad;
+use super::super::model::Model;
+use super::Unsupported;
+
+const INPUT_LOG: &str = "Input Log.txt";
+const HEADER: &str = "Header.txt";
+
+fn entry(archive: &mut zip::ZipArchive<io::Cursor<&[u8]>>, name: &str) -> Result<String, io::Error> {
+    let mut file = archive.by_name(name).map_err(|e| super::invalid(&format!("BK2 entry {} => {}", name, e)))?;
+    let mut text = String::new();
+    file.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+fn button(name: &str) -> Option<u8> {
+    match name {
+        "Right" => Some(joypad::RIGHT),
+        "Left" => Some(joypad::LEFT),
+        "Up" => Some(joypad::UP),
+        "Down" => Some(joypad::DOWN),
+        "A" => Some(joypad::A),
+        "B" => Some(joypad::B),
+        "Select" => Some(joypad::SELECT),
+        "Start" => Some(joypad::START),
+        _ => None,
+    }
+}
+
+/// "Key value" lines of Header.txt
+fn header_fields(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter_map(|line| {
+            let mut parts = line.trim().splitn(2, ' ');
+            Some((parts.next()?.to_string(), parts.next().unwrap_or("").trim().to_string()))
+        })
+        .collect()
+}
+
+/// button names of the LogKey line, "LogKey:#P1 Up|P1 Down|...|Power|#", in
+/// the order their mnemonics appear on every input line
+fn log_key(text: &str) -> Result<Vec<String>, io::Error> {
+    let key = text
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("LogKey:"))
+        .ok_or_else(|| super::invalid("BK2 input log has no LogKey line"))?;
+    Ok(key.split(['#', '|']).filter(|n| !n.is_empty()).map(|n| n.to_string()).collect())
+}
+
+/// BizHawk movie: a zip archive whose Input Log.txt holds one |...| line of
+/// button mnemonics per frame, '.' for released
+pub fn parse(data: &[u8], rom_sha1: &str, unsupported: &mut Unsupported) -> Result<(Model, Vec<u8>), io::Error> {
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(data)).map_err(|e| super::invalid(&e.to_string()))?;
+    let fields = header_fields(&entry(&mut archive, HEADER)?);
+    let log = entry(&mut archive, INPUT_LOG)?;
+
+    let platform = fields.get("Platform").map(String::as_str).unwrap_or("");
+    let model = match platform {
+        "GB" => Model::Dmg,
+        "GBC" => Model::Cgb,
+        "SGB" => {
+            unsupported.add("recorded with SGB features".to_string());
+            Model::Sgb
+        },
+        _ => {
+            unsupported.add(format!("recorded on platform {:?}", platform));
+            Model::Dmg
+        },
+    };
+    for field in &["StartsFromSavestate", "StartsFromSaveRam"] {
+        if fields.get(*field).is_some_and(|v| v.eq_ignore_ascii_case("true")) {
+            unsupported.add(format!("{} is set", field));
+        }
+    }
+    if let Some(sha1) = fields.get("SHA1") {
+        if !sha1.eq_ignore_ascii_case(rom_sha1) {
+            warn!("movie recorded on a rom with sha1 {}, running {}", sha1.to_lowercase(), rom_sha1);
+        }
+    }
+
+    let names = log_key(&log)?;
+    let mut inputs = Vec::new();
+    for line in log.lines().map(str::trim).filter(|l| l.starts_with('|')) {
+        let frame = inputs.len();
+        let mnemonics: Vec<char> = line.chars().filter(|c| *c != '|').collect();
+        if mnemonics.len() != names.len() {
+            return Err(super::invalid(&format!(
+                "BK2 frame {} has {} inputs where the LogKey names {}",
+                frame, mnemonics.len(), names.len()
+            )));
+        }
+        let mut pressed = 0;
+        for (name, mnemonic) in names.iter().zip(mnemonics) {
+            if mnemonic == '.' {
+                continue;
+            }
+            let name = name.strip_prefix("P1 ").unwrap_or(name);
+            match button(name) {
+                Some(b) => pressed |= b,
+                None if name == "Power" || name == "Reset" => {
+                    // BizHawk logs the initial power on as a press on the first frame
+                    if frame > 0 {
+                        unsupported.add(format!("{} on frame {}", name.to_lowercase(), frame));
+                    }
+                },
+                None => unsupported.add(format!("presses {:?} on frame {}", name, frame)),
+            }
+        }
+        inputs.push(pressed);
+    }
+    Ok((model, inputs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn bk2(platform: &str, key: &str, lines: &[&str]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        zip.start_file(HEADER, options).unwrap();
+        writeln!(zip, "MovieVersion BizHawk v2.0\nPlatform {}", platform).unwrap();
+        zip.start_file(INPUT_LOG, options).unwrap();
+        writeln!(zip, "[Input]\nLogKey:{}", key).unwrap();
+        for line in lines {
+            writeln!(zip, "{}", line).unwrap();
+        }
+        writeln!(zip, "[/Input]").unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_button_mapping() {
+        let key = "#P1 Up|P1 Down|P1 Left|P1 Right|P1 Start|P1 Select|P1 B|P1 A|P1 Power|#";
+        let lines = ["|........P|", "|U........|", "|.D.R.....|", "|..L.S..A.|", "|.....sB..|", "|UDLRSsBA.|"];
+        let mut unsupported = Unsupported::default();
+        let (model, inputs) = parse(&bk2("GBC", key, &lines), "", &mut unsupported).unwrap();
+
+        assert_eq!(model, Model::Cgb);
+        assert_eq!(inputs, vec![
+            0,
+            joypad::UP,
+            joypad::DOWN | joypad::RIGHT,
+            joypad::LEFT | joypad::START | joypad::A,
+            joypad::SELECT | joypad::B,
+            0xFF,
+        ]);
+        // power on the first frame is the console being switched on
+        assert!(unsupported.features.is_empty());
+    }
+
+    #[test]
+    fn test_unsupported_inputs() {
+        let key = "#Up|Down|Left|Right|Start|Select|B|A|Power|Reset|#";
+        let lines = ["|..........|", "|........P.|", "|.........r|"];
+        let mut unsupported = Unsupported::default();
+        let (model, inputs) = parse(&bk2("SGB", key, &lines), "", &mut unsupported).unwrap();
+
+        assert_eq!(model, Model::Sgb);
+        assert_eq!(inputs, vec![0, 0, 0]);
+        assert_eq!(unsupported.features, vec!["recorded with SGB features", "power on frame 1", "reset on frame 2"]);
+        assert!(parse(&bk2("GB", key, &["|....|"]), "", &mut Unsupported::default()).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::cartridge::Header;
+use super::model::Model;
+use super::state::{StateHeader, StateReader, StateWriter};
+
+mod bk2;
+mod vbm;
+
+const MAGIC: &[u8; 8] = b"GBOYMOVI";
+const MOVIE_VERSION: u16 = 1;
+
+/// joypad state for every frame, from power on or from an embedded save
+/// state, with machine hashes every hash_interval frames to catch desyncs
+#[derive(Debug, Clone)]
+pub struct Movie {
+    /// rom, model and state format the movie was recorded with
+    pub header: StateHeader,
+    /// save state the movie starts from, None for power on
+    pub start_state: Option<Vec<u8>>,
+    /// frames between hashes, 0 when there are none
+    pub hash_interval: usize,
+    /// pressed buttons, see memorybus::joypad, one byte per frame
+    pub inputs: Vec<u8>,
+    /// crc32 of the machine state at frames hash_interval, 2 * hash_interval...
+    pub hashes: Vec<u32>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// features of a foreign movie this emulator can't replay, an import fails
+/// listing all of them rather than desyncing somewhere along the way
+#[derive(Default)]
+pub(crate) struct Unsupported {
+    features: Vec<String>,
+}
+
+impl Unsupported {
+    fn add(&mut self, feature: String) {
+        self.features.push(feature);
+    }
+
+    fn check(self) -> Result<(), io::Error> {
+        const SHOWN: usize = 10;
+        match self.features.len() {
+            0 => Ok(()),
+            n if n <= SHOWN => Err(invalid(&format!("unsupported movie features => {}", self.features.join(", ")))),
+            n => Err(invalid(&format!(
+                "unsupported movie features => {} and {} more",
+                self.features[..SHOWN].join(", "),
+                n - SHOWN
+            ))),
+        }
+    }
+}
+
+impl Movie {
+    pub fn new(header: StateHeader, start_state: Option<Vec<u8>>, hash_interval: usize) -> Movie {
+        Movie {
+            header,
+            start_state,
+            hash_interval,
+            inputs: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::default();
+        for b in MAGIC {
+            w.u8(*b);
+        }
+        w.u16(MOVIE_VERSION);
+        self.header.write(&mut w);
+        w.bool(self.start_state.is_some());
+        if let Some(state) = &self.start_state {
+            w.bytes(state);
+        }
+        w.u32(self.hash_interval as u32);
+        w.bytes(&self.inputs);
+        w.u32(self.hashes.len() as u32);
+        for h in &self.hashes {
+            w.u32(*h);
+        }
+        w.into_bytes()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, io::Error> {
+        let mut r = StateReader::new(data);
+        for b in MAGIC {
+            if r.u8().ok() != Some(*b) {
+                return Err(invalid("not a gboy movie"));
+            }
+        }
+        let version = r.u16()?;
+        if version != MOVIE_VERSION {
+            return Err(invalid(&format!("movie version {} is not supported, this build reads version {}", version, MOVIE_VERSION)));
+        }
+        let header = StateHeader::read(&mut r)?;
+        let start_state = if r.bool()? { Some(r.bytes()?.to_vec()) } else { None };
+        let hash_interval = r.u32()? as usize;
+        let inputs = r.bytes()?.to_vec();
+        let hashes = (0..r.u32()?).map(|_| r.u32()).collect::<Result<Vec<u32>, io::Error>>()?;
+        Ok(Movie {
+            header,
+            start_state,
+            hash_interval,
+            inputs,
+            hashes,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// loads a gboy movie, or imports a BizHawk .bk2 or VisualBoyAdvance .vbm
+    /// one for the running rom. Imported movies start at power on and carry
+    /// no hashes, so they play back but can't be verified
+    pub fn open(path: &Path, running: &StateHeader, cartridge: &Header) -> Result<Movie, io::Error> {
+        let data = fs::read(path)?;
+        let mut unsupported = Unsupported::default();
+        let (model, inputs) = if vbm::is_vbm(&data) {
+            debug!("{:?} is a VBM movie", path);
+            vbm::parse(&data, cartridge, &mut unsupported)?
+        } else if data.starts_with(b"PK\x03\x04") {
+            debug!("{:?} is a BK2 movie", path);
+            bk2::parse(&data, &running.rom_sha1, &mut unsupported)?
+        } else {
+            return Movie::from_bytes(&data);
+        };
+        unsupported.check()?;
+
+        let compatible = model == Model::Cgb && running.model == Model::CgbCompat;
+        if model != running.model && !compatible {
+            warn!("movie recorded on {}, emulating {}, it will likely desync", model, running.model);
+        }
+        info!("imported {} frames of input from {:?}", inputs.len(), path);
+        let mut movie = Movie::new(running.clone(), None, 0);
+        movie.inputs = inputs;
+        Ok(movie)
+    }
+}
@@ -0,0 +1,177 @@
+use std::io;
+
+use super::super::cartridge::Header;
+use super::super::memorybus::joypad;
+use super::super::model::Model;
+use super::Unsupported;
+
+const MAGIC: &[u8; 4] = b"VBM\x1A";
+const HEADER_SIZE: usize = 0x100;
+
+const START_SNAPSHOT: u8 = 0x01;
+const START_SRAM: u8 = 0x02;
+
+const SYSTEM_GBA: u8 = 0x01;
+const SYSTEM_GBC: u8 = 0x02;
+const SYSTEM_SGB: u8 = 0x08;
+
+// per frame input word, the low byte matches the GB joypad order
+const BUTTON_A: u16 = 0x0001;
+const BUTTON_B: u16 = 0x0002;
+const BUTTON_SELECT: u16 = 0x0004;
+const BUTTON_START: u16 = 0x0008;
+const BUTTON_RIGHT: u16 = 0x0010;
+const BUTTON_LEFT: u16 = 0x0020;
+const BUTTON_UP: u16 = 0x0040;
+const BUTTON_DOWN: u16 = 0x0080;
+const BUTTON_R: u16 = 0x0100;
+const BUTTON_L: u16 = 0x0200;
+const BUTTON_RESET: u16 = 0x0400 | 0x0800;
+// motion sensor bits of GBA carts
+const BUTTON_MOTION: u16 = 0xF000;
+
+fn u16_at(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+fn buttons(input: u16) -> u8 {
+    [
+        (BUTTON_RIGHT, joypad::RIGHT),
+        (BUTTON_LEFT, joypad::LEFT),
+        (BUTTON_UP, joypad::UP),
+        (BUTTON_DOWN, joypad::DOWN),
+        (BUTTON_A, joypad::A),
+        (BUTTON_B, joypad::B),
+        (BUTTON_SELECT, joypad::SELECT),
+        (BUTTON_START, joypad::START),
+    ]
+    .iter()
+    .filter(|(bit, _)| input & bit != 0)
+    .fold(0, |pressed, (_, button)| pressed | button)
+}
+
+pub fn is_vbm(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// VisualBoyAdvance movie: a 256 bytes header then one input word per frame
+/// and enabled controller
+pub fn parse(data: &[u8], cartridge: &Header, unsupported: &mut Unsupported) -> Result<(Model, Vec<u8>), io::Error> {
+    if !is_vbm(data) || data.len() < HEADER_SIZE {
+        return Err(super::invalid("not a VBM movie"));
+    }
+    let frames = u32_at(data, 0x0C) as usize;
+    let start = data[0x14];
+    let controllers = data[0x15];
+    let system = data[0x16];
+    let header_checksum = data[0x31];
+    let global_checksum = u16_at(data, 0x32);
+    let input_offset = u32_at(data, 0x3C) as usize;
+
+    if start & START_SNAPSHOT != 0 {
+        unsupported.add("starts from a VBA snapshot".to_string());
+    } else if start & START_SRAM != 0 {
+        unsupported.add("starts from VBA save ram".to_string());
+    }
+    if system & SYSTEM_GBA != 0 {
+        unsupported.add("recorded on GBA".to_string());
+    }
+    if system & SYSTEM_SGB != 0 {
+        unsupported.add("recorded with SGB features".to_string());
+    }
+    let ports = (controllers & 0x0F).count_ones() as usize;
+    if controllers & 0x0F != 0x01 {
+        unsupported.add(format!("uses controllers {:04b}, only the first one is emulated", controllers & 0x0F));
+    }
+    if header_checksum != cartridge.header_checksum || global_checksum.swap_bytes() != cartridge.global_checksum {
+        warn!(
+            "movie recorded on a rom with checksums {:02X}/{:04X}, running {:02X}/{:04X}",
+            header_checksum, global_checksum.swap_bytes(), cartridge.header_checksum, cartridge.global_checksum
+        );
+    }
+
+    let stride = 2 * ports.max(1);
+    let end = input_offset + frames * stride;
+    if input_offset < HEADER_SIZE || end > data.len() {
+        return Err(super::invalid(&format!("VBM input of {} frames runs past the end of the file", frames)));
+    }
+    let mut inputs = Vec::with_capacity(frames);
+    for (frame, chunk) in data[input_offset..end].chunks(stride).enumerate() {
+        let input = u16_at(chunk, 0);
+        if input & BUTTON_RESET != 0 {
+            unsupported.add(format!("resets on frame {}", frame));
+        }
+        if input & (BUTTON_L | BUTTON_R) != 0 {
+            unsupported.add(format!("presses GBA L/R on frame {}", frame));
+        }
+        if input & BUTTON_MOTION != 0 {
+            unsupported.add(format!("uses the motion sensor on frame {}", frame));
+        }
+        inputs.push(buttons(input));
+    }
+
+    let model = if system & SYSTEM_GBC != 0 { Model::Cgb } else { Model::Dmg };
+    Ok((model, inputs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vbm(controllers: u8, system: u8, words: &[u16]) -> Vec<u8> {
+        let stride = (controllers & 0x0F).count_ones().max(1) as usize;
+        let mut data = vec![0; HEADER_SIZE];
+        data[..4].copy_from_slice(MAGIC);
+        data[0x0C..0x10].copy_from_slice(&((words.len() / stride) as u32).to_le_bytes());
+        data[0x15] = controllers;
+        data[0x16] = system;
+        data[0x3C..0x40].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        for word in words {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_button_mapping() {
+        let words = [0x0000, BUTTON_A, BUTTON_B | BUTTON_SELECT, BUTTON_START | BUTTON_RIGHT, BUTTON_LEFT | BUTTON_UP | BUTTON_DOWN, 0x00FF];
+        let mut unsupported = Unsupported::default();
+        let (model, inputs) = parse(&vbm(0x01, SYSTEM_GBC, &words), &Header::default(), &mut unsupported).unwrap();
+
+        assert_eq!(model, Model::Cgb);
+        assert_eq!(inputs, vec![
+            0,
+            joypad::A,
+            joypad::B | joypad::SELECT,
+            joypad::START | joypad::RIGHT,
+            joypad::LEFT | joypad::UP | joypad::DOWN,
+            0xFF,
+        ]);
+        assert!(unsupported.features.is_empty());
+    }
+
+    #[test]
+    fn test_unsupported_inputs() {
+        // two controllers, only the first word of each frame is read
+        let words = [BUTTON_A, BUTTON_B, BUTTON_RESET | BUTTON_UP, 0x0000, BUTTON_L, 0x0000, 0x1000, 0x0000];
+        let mut unsupported = Unsupported::default();
+        let (model, inputs) = parse(&vbm(0x03, 0x00, &words), &Header::default(), &mut unsupported).unwrap();
+
+        assert_eq!(model, Model::Dmg);
+        assert_eq!(inputs, vec![joypad::A, joypad::UP, 0, 0]);
+        assert_eq!(unsupported.features, vec![
+            "uses controllers 0011, only the first one is emulated",
+            "resets on frame 1",
+            "presses GBA L/R on frame 2",
+            "uses the motion sensor on frame 3",
+        ]);
+
+        let mut truncated = vbm(0x01, 0x00, &[BUTTON_A, BUTTON_B]);
+        truncated.pop();
+        assert!(parse(&truncated, &Header::default(), &mut Unsupported::default()).is_err());
+    }
+}
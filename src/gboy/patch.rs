@@ -0,0 +1,391 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::rom;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: usize = 0x454F46;
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// source, target and patch crc32 closing UPS and BPS patches
+const FOOTER_SIZE: usize = 12;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid patch, {}", msg))
+}
+
+/// .ips, .ups or .bps file next to the rom with the same stem, first found wins
+pub fn find(gamerom: &Path) -> Option<PathBuf> {
+    ["ips", "ups", "bps"].iter()
+        .map(|e| rom::sibling(gamerom, e))
+        .find(|p| p.exists())
+}
+
+pub fn load(path: &Path, gamerom: Vec<u8>) -> Result<Vec<u8>, io::Error> {
+    let patch = fs::read(path)?;
+    apply(gamerom, &patch)
+}
+
+/// patches a rom, the format is found by its magic number
+pub fn apply(gamerom: Vec<u8>, patch: &[u8]) -> Result<Vec<u8>, io::Error> {
+    if patch.starts_with(IPS_MAGIC) {
+        ips(gamerom, patch)
+    } else if patch.starts_with(UPS_MAGIC) {
+        ups(&gamerom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        bps(&gamerom, patch)
+    } else {
+        Err(invalid("unknown format"))
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, io::Error> {
+        let b = *self.data.get(self.pos).ok_or_else(|| invalid("unexpected end"))?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], io::Error> {
+        let b = self.data.get(self.pos..self.pos.saturating_add(len)).ok_or_else(|| invalid("unexpected end"))?;
+        self.pos += len;
+        Ok(b)
+    }
+
+    /// big endian, as IPS uses
+    fn be(&mut self, len: usize) -> Result<usize, io::Error> {
+        Ok(self.bytes(len)?.iter().fold(0, |x, b| x << 8 | *b as usize))
+    }
+
+    /// UPS/BPS variable length number
+    fn number(&mut self) -> Result<usize, io::Error> {
+        let overflow = || invalid("number overflow");
+        let mut data = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let x = self.byte()?;
+            let digit = ((x & 0x7F) as usize).checked_mul(shift).ok_or_else(overflow)?;
+            data = data.checked_add(digit).ok_or_else(overflow)?;
+            if x & 0x80 != 0x0 {
+                return Ok(data);
+            }
+            shift = shift.checked_mul(0x80).ok_or_else(overflow)?;
+            data = data.checked_add(shift).ok_or_else(overflow)?;
+        }
+    }
+
+    /// size of the patched rom, refused past what a cartridge maps
+    fn target_size(&mut self) -> Result<usize, io::Error> {
+        let size = self.number()?;
+        if size as u64 > rom::MAX_ROM_SIZE {
+            return Err(invalid("patched rom too big"));
+        }
+        Ok(size)
+    }
+}
+
+/// BPS relative offset, the lowest bit of d holds the sign, within 0..=len
+fn relative(offset: usize, d: usize, len: usize) -> Result<usize, io::Error> {
+    let moved = if d & 1 == 1 { offset.checked_sub(d >> 1) } else { offset.checked_add(d >> 1) };
+    moved.filter(|o| *o <= len).ok_or_else(|| invalid("copy out of bounds"))
+}
+
+fn ips(mut gamerom: Vec<u8>, patch: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut r = Reader { data: patch, pos: IPS_MAGIC.len() };
+    loop {
+        let offset = r.be(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let size = r.be(2)?;
+        let (size, data) = if size == 0x0 {
+            // run length encoded record
+            let size = r.be(2)?;
+            (size, vec![r.byte()?; size])
+        } else {
+            (size, r.bytes(size)?.to_vec())
+        };
+        if (offset + size) as u64 > rom::MAX_ROM_SIZE {
+            return Err(invalid("patched rom too big"));
+        }
+        if gamerom.len() < offset + size {
+            gamerom.resize(offset + size, 0x0);
+        }
+        gamerom[offset..offset + size].copy_from_slice(&data);
+    }
+    // optional truncation extension
+    if let Ok(len) = r.be(3) {
+        gamerom.truncate(len);
+    }
+    Ok(gamerom)
+}
+
+fn footer(patch: &[u8]) -> Result<(u32, u32), io::Error> {
+    if patch.len() < FOOTER_SIZE {
+        return Err(invalid("missing checksums"));
+    }
+    let crc = |at: usize| {
+        let mut b = [0u8; 4];
+        b.copy_from_slice(&patch[at..at + 4]);
+        u32::from_le_bytes(b)
+    };
+    let end = patch.len() - FOOTER_SIZE;
+    if crc32fast::hash(&patch[..patch.len() - 4]) != crc(end + 8) {
+        return Err(invalid("patch checksum mismatch"));
+    }
+    Ok((crc(end), crc(end + 4)))
+}
+
+fn check_source(gamerom: &[u8], expected: u32) -> Result<(), io::Error> {
+    if crc32fast::hash(gamerom) != expected {
+        return Err(invalid("rom checksum mismatch, patch is meant for another rom"));
+    }
+    Ok(())
+}
+
+fn check_target(target: &[u8], expected: u32) -> Result<(), io::Error> {
+    if crc32fast::hash(target) != expected {
+        return Err(invalid("patched rom checksum mismatch"));
+    }
+    Ok(())
+}
+
+fn ups(gamerom: &[u8], patch: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let (source_crc, target_crc) = footer(patch)?;
+    check_source(gamerom, source_crc)?;
+
+    let end = patch.len() - FOOTER_SIZE;
+    let mut r = Reader { data: &patch[..end], pos: UPS_MAGIC.len() };
+    let _source_size = r.number()?;
+    let target_size = r.target_size()?;
+    let mut target = gamerom.to_vec();
+    target.resize(target_size, 0x0);
+
+    let mut pos = 0usize;
+    while r.pos < end {
+        pos = pos.checked_add(r.number()?).ok_or_else(|| invalid("number overflow"))?;
+        loop {
+            let x = r.byte()?;
+            if x == 0x0 {
+                pos = pos.saturating_add(1);
+                break;
+            }
+            if pos < target_size {
+                target[pos] ^= x;
+            }
+            pos = pos.saturating_add(1);
+        }
+    }
+    check_target(&target, target_crc)?;
+    Ok(target)
+}
+
+fn bps(gamerom: &[u8], patch: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let (source_crc, target_crc) = footer(patch)?;
+    check_source(gamerom, source_crc)?;
+
+    let end = patch.len() - FOOTER_SIZE;
+    let mut r = Reader { data: &patch[..end], pos: BPS_MAGIC.len() };
+    let _source_size = r.number()?;
+    let target_size = r.target_size()?;
+    let metadata = r.number()?;
+    r.bytes(metadata)?;
+
+    let mut target: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_relative = 0;
+    let mut target_relative = 0;
+    let out_of_bounds = || invalid("copy out of bounds");
+    while r.pos < end {
+        let data = r.number()?;
+        let length = (data >> 2) + 1;
+        if length > target_size - target.len() {
+            return Err(invalid("patched rom bigger than declared"));
+        }
+        match data & 0b11 {
+            // source read
+            0 => {
+                let at = target.len();
+                let bytes = gamerom.get(at..at + length).ok_or_else(out_of_bounds)?;
+                target.extend_from_slice(bytes);
+            },
+            // target read
+            1 => target.extend_from_slice(r.bytes(length)?),
+            // source copy
+            2 => {
+                source_relative = relative(source_relative, r.number()?, gamerom.len())?;
+                let bytes = gamerom.get(source_relative..source_relative + length).ok_or_else(out_of_bounds)?;
+                target.extend_from_slice(bytes);
+                source_relative += length;
+            },
+            // target copy, may overlap what it's writing
+            _ => {
+                target_relative = relative(target_relative, r.number()?, target.len())?;
+                for _ in 0..length {
+                    let b = *target.get(target_relative).ok_or_else(out_of_bounds)?;
+                    target.push(b);
+                    target_relative += 1;
+                }
+            },
+        }
+    }
+    if target.len() != target_size {
+        return Err(invalid("patched rom size mismatch"));
+    }
+    check_target(&target, target_crc)?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source() -> Vec<u8> {
+        (0..32).collect()
+    }
+
+    /// UPS/BPS variable length number
+    fn number(patch: &mut Vec<u8>, mut n: usize) {
+        loop {
+            let x = (n & 0x7F) as u8;
+            n >>= 7;
+            if n == 0 {
+                patch.push(0x80 | x);
+                return;
+            }
+            patch.push(x);
+            n -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        let crc = crc32fast::hash(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_number_round_trip_and_overflow() {
+        for n in [0, 1, 0x7F, 0x80, 0x4000, 0x123456, usize::MAX >> 8] {
+            let mut patch = Vec::new();
+            number(&mut patch, n);
+            assert_eq!(Reader { data: &patch, pos: 0 }.number().unwrap(), n);
+        }
+        let endless = [0x7F; 16];
+        assert!(Reader { data: &endless, pos: 0 }.number().is_err());
+    }
+
+    #[test]
+    fn test_ips() {
+        let mut patch = IPS_MAGIC.to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x02, 0xAA, 0xBB]);
+        // run length encoded record growing the rom
+        patch.extend_from_slice(&[0x00, 0x00, 0x1E, 0x00, 0x00, 0x00, 0x04, 0xCC]);
+        patch.extend_from_slice(b"EOF");
+        let mut expected = source();
+        expected[2..4].copy_from_slice(&[0xAA, 0xBB]);
+        expected.truncate(30);
+        expected.extend_from_slice(&[0xCC; 4]);
+        assert_eq!(apply(source(), &patch).unwrap(), expected);
+
+        // truncation extension
+        let mut truncating = patch.clone();
+        truncating.extend_from_slice(&[0x00, 0x00, 0x08]);
+        assert_eq!(apply(source(), &truncating).unwrap(), expected[..8]);
+
+        assert!(apply(source(), &patch[..patch.len() - 3]).is_err());
+        let mut too_big = IPS_MAGIC.to_vec();
+        too_big.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00]);
+        too_big.extend_from_slice(b"EOF");
+        assert!(apply(source(), &too_big).is_err());
+    }
+
+    fn ups_patch(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = UPS_MAGIC.to_vec();
+        number(&mut patch, source.len());
+        number(&mut patch, target.len());
+        // one hunk xoring bytes 4..=5, then the grown tail
+        number(&mut patch, 4);
+        patch.extend_from_slice(&[source[4] ^ target[4], source[5] ^ target[5], 0x00]);
+        number(&mut patch, source.len() - 7);
+        patch.extend_from_slice(&target[source.len()..]);
+        patch.push(0x00);
+        with_footer(patch, source, target)
+    }
+
+    #[test]
+    fn test_ups() {
+        let mut target = source();
+        target[4] = 0xF0;
+        target[5] = 0x0F;
+        target.extend_from_slice(&[0x11, 0x22]);
+        let patch = ups_patch(&source(), &target);
+        assert_eq!(apply(source(), &patch).unwrap(), target);
+
+        let mut other = source();
+        other[0] = 0xFF;
+        assert!(apply(other, &patch).is_err());
+        assert!(apply(source(), &patch[..patch.len() - 1]).is_err());
+        let mut corrupted = patch.clone();
+        corrupted[8] ^= 0x1;
+        assert!(apply(source(), &corrupted).is_err());
+    }
+
+    fn bps_patch(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        number(&mut patch, source.len());
+        number(&mut patch, target.len());
+        number(&mut patch, 0);
+        patch.extend_from_slice(actions);
+        with_footer(patch, source, target)
+    }
+
+    #[test]
+    fn test_bps() {
+        let source = source();
+        let mut target = source[..4].to_vec();
+        target.extend_from_slice(&[0xAA, 0xBB]);
+        target.extend_from_slice(&source[10..13]);
+        target.extend_from_slice(&[12; 4]);
+
+        let mut actions = Vec::new();
+        // source read 4
+        number(&mut actions, 3 << 2);
+        // target read 2
+        number(&mut actions, 1 << 2 | 1);
+        actions.extend_from_slice(&[0xAA, 0xBB]);
+        // source copy 3 from 10
+        number(&mut actions, 2 << 2 | 2);
+        number(&mut actions, 10 << 1);
+        // target copy 4 from 8, overlapping what it writes
+        number(&mut actions, 3 << 2 | 3);
+        number(&mut actions, 8 << 1);
+        let patch = bps_patch(&source, &target, &actions);
+        assert_eq!(apply(source.clone(), &patch).unwrap(), target);
+
+        assert!(apply(source[1..].to_vec(), &patch).is_err());
+        assert!(apply(source.clone(), &patch[..patch.len() - 1]).is_err());
+
+        // an action past the declared size is refused before growing the rom
+        let mut long = Vec::new();
+        number(&mut long, (rom::MAX_ROM_SIZE as usize) << 2 | 1);
+        assert!(apply(source.clone(), &bps_patch(&source, &target, &long)).is_err());
+        // copies before the start
+        let mut before = Vec::new();
+        number(&mut before, 2);
+        number(&mut before, 1 << 1 | 1);
+        assert!(apply(source.clone(), &bps_patch(&source, &target, &before)).is_err());
+        // a target bigger than any cartridge
+        let mut huge = BPS_MAGIC.to_vec();
+        number(&mut huge, source.len());
+        number(&mut huge, 1 << 40);
+        assert!(apply(source.clone(), &with_footer(huge, &source, &target)).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+
+/// how much history is kept, whichever limit is hit first drops the oldest snapshots
+#[derive(Debug, Clone)]
+pub struct RewindConfig {
+    /// frames between snapshots, 1 rewinds frame by frame
+    pub interval: usize,
+    /// seconds of play kept, at 60 frames a second
+    pub seconds: usize,
+    /// memory budget for snapshots in bytes
+    pub memory: usize,
+}
+
+impl Default for RewindConfig {
+    fn default() -> RewindConfig {
+        RewindConfig {
+            interval: 1,
+            seconds: 10,
+            memory: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// older snapshot as XOR against the next one, run length encoded
+struct Delta {
+    len: usize,
+    runs: Vec<u8>,
+}
+
+/// ring of machine snapshots, the newest kept whole and every older one as
+/// a compressed delta against its successor, so stepping back only walks one
+/// delta and dropping the oldest is free
+pub struct Rewind {
+    config: RewindConfig,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+    memory_used: usize,
+}
+
+/// (count, value) pairs, XOR deltas are mostly long runs of zeros
+fn rle_encode(data: &[u8]) -> Vec<u8> {
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let value = data[i];
+        let mut count = 1;
+        while count < 255 && i + count < data.len() && data[i + count] == value {
+            count += 1;
+        }
+        runs.push(count as u8);
+        runs.push(value);
+        i += count;
+    }
+    runs
+}
+
+fn rle_decode(runs: &[u8], len: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(len);
+    for run in runs.chunks(2) {
+        data.extend(std::iter::repeat_n(run[1], run[0] as usize));
+    }
+    data
+}
+
+/// XOR of two snapshots, the shorter one padded with zeros
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let len = a.len().max(b.len());
+    (0..len)
+        .map(|i| a.get(i).cloned().unwrap_or(0) ^ b.get(i).cloned().unwrap_or(0))
+        .collect()
+}
+
+impl Rewind {
+    pub fn new(config: RewindConfig) -> Rewind {
+        Rewind {
+            config,
+            latest: None,
+            deltas: VecDeque::new(),
+            memory_used: 0,
+        }
+    }
+
+    pub fn interval(&self) -> usize {
+        self.config.interval.max(1)
+    }
+
+    fn capacity(&self) -> usize {
+        (self.config.seconds * 60 / self.interval()).max(1)
+    }
+
+    /// snapshots that can still be stepped back to
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.memory_used + self.latest.as_ref().map_or(0, |l| l.len())
+    }
+
+    pub fn push(&mut self, snapshot: Vec<u8>) {
+        if let Some(latest) = self.latest.take() {
+            let delta = Delta {
+                len: latest.len(),
+                runs: rle_encode(&xor(&latest, &snapshot)),
+            };
+            self.memory_used += delta.runs.len();
+            self.deltas.push_back(delta);
+        }
+        self.latest = Some(snapshot);
+
+        while self.deltas.len() > self.capacity() || (self.memory_used() > self.config.memory && !self.deltas.is_empty()) {
+            if let Some(oldest) = self.deltas.pop_front() {
+                self.memory_used -= oldest.runs.len();
+            }
+        }
+    }
+
+    /// previous snapshot, which becomes the newest one, None once history runs out
+    pub fn step_back(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        self.memory_used -= delta.runs.len();
+        let latest = self.latest.take().unwrap_or_default();
+        let mut previous = xor(&latest, &rle_decode(&delta.runs, latest.len().max(delta.len)));
+        previous.truncate(delta.len);
+        self.latest = Some(previous.clone());
+        Some(previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_back_through_snapshots() {
+        let mut rewind = Rewind::new(RewindConfig { interval: 1, seconds: 1, memory: 1 << 20 });
+        let snapshots: Vec<Vec<u8>> = (0..70u8)
+            .map(|i| {
+                let mut s = vec![0; 1000 + (i as usize % 3)];
+                s[i as usize] = i;
+                s[999] = 255 - i;
+                s
+            })
+            .collect();
+        for s in &snapshots {
+            rewind.push(s.clone());
+        }
+        // one second at one snapshot a frame
+        assert_eq!(rewind.len(), 60);
+        for s in snapshots[9..69].iter().rev() {
+            assert_eq!(rewind.step_back().as_ref(), Some(s));
+        }
+        assert_eq!(rewind.step_back(), None);
+    }
+}
@@ -0,0 +1,141 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const ZIP_MAGIC: [u8; 4] = [0x50, 0x4B, 0x03, 0x04];
+/// biggest rom a cartridge maps, 512 MBC5 banks
+pub const MAX_ROM_SIZE: u64 = 8 * 1024 * 1024;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// reads a decompressed rom, refusing to inflate past MAX_ROM_SIZE whatever
+/// the archive claims
+fn read_rom<R: Read>(reader: R) -> Result<Vec<u8>, io::Error> {
+    let mut rom = Vec::new();
+    reader.take(MAX_ROM_SIZE + 1).read_to_end(&mut rom)?;
+    if rom.len() as u64 > MAX_ROM_SIZE {
+        return Err(invalid(format!("rom bigger than {} bytes", MAX_ROM_SIZE)));
+    }
+    Ok(rom)
+}
+
+fn is_rom_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.ends_with(".gb") || name.ends_with(".gbc")
+}
+
+/// file next to the rom sharing its stem, game.gb.gz gives game.<extension> as well
+pub fn sibling(gamerom: &Path, extension: &str) -> PathBuf {
+    match gamerom.extension() {
+        Some(e) if e == "gz" => gamerom.with_extension("").with_extension(extension),
+        _ => gamerom.with_extension(extension),
+    }
+}
+
+/// reads a rom, transparently decompressing gzip and zip files found by their
+/// magic number. Zip archives must hold a single .gb/.gbc entry unless zip_entry
+/// names the one to pick
+pub fn load(path: &Path, zip_entry: Option<&str>) -> Result<Vec<u8>, io::Error> {
+    let data = fs::read(path)?;
+    if data.starts_with(&GZIP_MAGIC) {
+        debug!("{:?} is gzip compressed", path);
+        return read_rom(GzDecoder::new(&data[..]));
+    }
+    if data.starts_with(&ZIP_MAGIC) {
+        debug!("{:?} is a zip archive", path);
+        return unzip(data, zip_entry);
+    }
+    if zip_entry.is_some() {
+        warn!("{:?} isn't a zip archive, ignoring zip entry", path);
+    }
+    if data.len() as u64 > MAX_ROM_SIZE {
+        return Err(invalid(format!("rom bigger than {} bytes", MAX_ROM_SIZE)));
+    }
+    Ok(data)
+}
+
+fn unzip(data: Vec<u8>, zip_entry: Option<&str>) -> Result<Vec<u8>, io::Error> {
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(data)).map_err(|e| invalid(e.to_string()))?;
+    let name = match zip_entry {
+        Some(n) => n.to_string(),
+        None => {
+            let roms: Vec<String> = (0..archive.len())
+                .filter_map(|i| archive.by_index(i).ok().map(|f| f.name().to_string()))
+                .filter(|n| is_rom_name(n))
+                .collect();
+            match roms.len() {
+                0 => return Err(invalid(String::from("no .gb/.gbc entry in zip archive"))),
+                1 => roms[0].clone(),
+                _ => return Err(invalid(format!("several roms in zip archive, pick one with --zip-entry => {}", roms.join(", ")))),
+            }
+        },
+    };
+    let file = archive.by_name(&name).map_err(|e| invalid(format!("zip entry {} => {}", name, e)))?;
+    debug!("unzipping {}", name);
+    read_rom(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn temp(name: &str, data: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("gboy-rom-{}-{}", std::process::id(), name));
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            writer.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn load_temp(name: &str, data: &[u8], zip_entry: Option<&str>) -> Result<Vec<u8>, io::Error> {
+        let path = temp(name, data);
+        let rom = load(&path, zip_entry);
+        fs::remove_file(&path).unwrap();
+        rom
+    }
+
+    #[test]
+    fn test_gzip() {
+        let rom: Vec<u8> = (0..0x8000).map(|i| i as u8).collect();
+        assert_eq!(load_temp("game.gb.gz", &gzip(&rom), None).unwrap(), rom);
+        assert_eq!(sibling(Path::new("game.gb.gz"), "sav"), PathBuf::from("game.sav"));
+
+        let bomb = gzip(&vec![0; MAX_ROM_SIZE as usize + 1]);
+        assert!(load_temp("bomb.gb.gz", &bomb, None).is_err());
+    }
+
+    #[test]
+    fn test_zip() {
+        let rom = vec![0x42; 0x8000];
+        let single = zip(&[("readme.txt", b"hi"), ("game.GB", &rom)]);
+        assert_eq!(load_temp("single.zip", &single, None).unwrap(), rom);
+
+        let several = zip(&[("a.gb", &rom), ("b.gbc", b"other")]);
+        assert!(load_temp("several.zip", &several, None).is_err());
+        assert_eq!(load_temp("several.zip", &several, Some("b.gbc")).unwrap(), b"other");
+        assert!(load_temp("several.zip", &several, Some("c.gb")).is_err());
+        assert!(load_temp("empty.zip", &zip(&[("readme.txt", b"hi")]), None).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::str::FromStr;
+
+pub use super::memorybus::sgb::{SGB_HEIGHT, SGB_WIDTH};
+
+/// plain grey levels for shades 0 to 3
+pub const DMG_GREYS: [[u8; 3]; 4] = [
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+    [0x00, 0x00, 0x00],
+];
+
+const DMG_GREEN: [[u8; 3]; 4] = [
+    [0x9B, 0xBC, 0x0F],
+    [0x8B, 0xAC, 0x0F],
+    [0x30, 0x62, 0x30],
+    [0x0F, 0x38, 0x0F],
+];
+
+const POCKET: [[u8; 3]; 4] = [
+    [0xC4, 0xCF, 0xA1],
+    [0x8B, 0x95, 0x6D],
+    [0x4D, 0x53, 0x3C],
+    [0x1F, 0x1F, 0x1F],
+];
+
+/// colors DMG shades are shown with
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum DmgPalette {
+    #[default]
+    Grey,
+    Green,
+    Pocket,
+    // lightest to darkest, RGB888
+    Custom([[u8; 3]; 4]),
+}
+
+impl DmgPalette {
+    pub fn colors(&self) -> [[u8; 3]; 4] {
+        match self {
+            DmgPalette::Grey => DMG_GREYS,
+            DmgPalette::Green => DMG_GREEN,
+            DmgPalette::Pocket => POCKET,
+            DmgPalette::Custom(colors) => *colors,
+        }
+    }
+
+    /// next built in palette, a custom one goes back to grey
+    pub fn next(&self) -> DmgPalette {
+        match self {
+            DmgPalette::Grey => DmgPalette::Green,
+            DmgPalette::Green => DmgPalette::Pocket,
+            _ => DmgPalette::Grey,
+        }
+    }
+}
+
+impl FromStr for DmgPalette {
+    type Err = String;
+    /// grey, green, pocket or four comma separated hex colors, lightest first
+    fn from_str(s: &str) -> Result<DmgPalette, String> {
+        match s.to_lowercase().as_str() {
+            "grey" | "gray" => return Ok(DmgPalette::Grey),
+            "green" => return Ok(DmgPalette::Green),
+            "pocket" => return Ok(DmgPalette::Pocket),
+            _ => {},
+        }
+        let hex: Vec<&str> = s.split(',').map(|c| c.trim().trim_start_matches('#')).collect();
+        if hex.len() != 4 {
+            return Err(format!("unknown palette {}, expected grey, green, pocket or 4 hex colors", s));
+        }
+        let mut colors = [[0; 3]; 4];
+        for (color, h) in colors.iter_mut().zip(hex) {
+            let rgb = u32::from_str_radix(h, 16).ok().filter(|_| h.len() == 6)
+                .ok_or_else(|| format!("bad palette color {}, expected RRGGBB", h))?;
+            *color = [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8];
+        }
+        Ok(DmgPalette::Custom(colors))
+    }
+}
+
+impl fmt::Display for DmgPalette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DmgPalette::Grey => write!(f, "grey"),
+            DmgPalette::Green => write!(f, "green"),
+            DmgPalette::Pocket => write!(f, "pocket"),
+            DmgPalette::Custom(colors) => {
+                let hex: Vec<String> = colors.iter()
+                    .map(|c| format!("{:02X}{:02X}{:02X}", c[0], c[1], c[2]))
+                    .collect();
+                write!(f, "{}", hex.join(","))
+            },
+        }
+    }
+}
+
+/// how CGB colors are turned into RGB888
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorCorrection {
+    // plain channel scaling, as bright and saturated as an emulator screen gets
+    #[default]
+    Off,
+    // washed out like the CGB lcd, channels bleed into each other
+    Lcd,
+}
+
+impl FromStr for ColorCorrection {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ColorCorrection, String> {
+        match s.to_lowercase().as_str() {
+            "off" | "none" => Ok(ColorCorrection::Off),
+            "lcd" => Ok(ColorCorrection::Lcd),
+            _ => Err(format!("unknown color correction {}, expected off or lcd", s)),
+        }
+    }
+}
+
+/// output stage applied to the framebuffer, hotkeys cycle through it
+#[derive(Debug, Clone, Default)]
+pub struct PostProcess {
+    pub palette: DmgPalette,
+    pub correction: ColorCorrection,
+}
+
+impl PostProcess {
+    pub fn cycle_palette(&mut self) {
+        self.palette = self.palette.next();
+        info!("palette {}", self.palette);
+    }
+
+    pub fn toggle_correction(&mut self) {
+        self.correction = match self.correction {
+            ColorCorrection::Off => ColorCorrection::Lcd,
+            ColorCorrection::Lcd => ColorCorrection::Off,
+        };
+        info!("color correction {:?}", self.correction);
+    }
+
+    pub fn shades(&self, framebuffer: &[u8]) -> Vec<u8> {
+        to_rgb_with(framebuffer, &self.palette)
+    }
+
+    pub fn colors(&self, colors: &[u16]) -> Vec<u8> {
+        match self.correction {
+            ColorCorrection::Off => rgb555_to_rgb(colors),
+            ColorCorrection::Lcd => correct_rgb555(colors),
+        }
+    }
+}
+
+/// expands a shades framebuffer into RGB888
+pub fn to_rgb_with(framebuffer: &[u8], palette: &DmgPalette) -> Vec<u8> {
+    let colors = palette.colors();
+    framebuffer.iter()
+        .flat_map(|s| colors[(*s & 0b11) as usize].iter().cloned())
+        .collect()
+}
+
+/// expands a CGB RGB555 framebuffer into RGB888
+pub fn rgb555_to_rgb(colors: &[u16]) -> Vec<u8> {
+    colors.iter()
+        .flat_map(|c| {
+            let channel = |shift: u16| {
+                let v = ((c >> shift) & 0x1F) as u8;
+                (v << 3) | (v >> 2)
+            };
+            vec![channel(0), channel(5), channel(10)]
+        })
+        .collect()
+}
+
+/// CGB lcd approximation, channels are decoded with the lcd gamma, mixed as
+/// the screen bleeds them into each other and encoded back for a 2.2 display
+pub fn correct_rgb555(colors: &[u16]) -> Vec<u8> {
+    const LCD_GAMMA: f64 = 4.0;
+    const DISPLAY_GAMMA: f64 = 2.2;
+    colors.iter()
+        .flat_map(|c| {
+            let linear = |shift: u16| (((c >> shift) & 0x1F) as f64 / 31.0).powf(LCD_GAMMA);
+            let (r, g, b) = (linear(0), linear(5), linear(10));
+            let mixed = [
+                (255.0 * r + 50.0 * g) / 255.0,
+                (10.0 * r + 230.0 * g + 30.0 * b) / 255.0,
+                (50.0 * r + 10.0 * g + 220.0 * b) / 255.0,
+            ];
+            // white ends up a little under full brightness, as dim as the screen
+            mixed.iter()
+                .map(|v| (v.powf(1.0 / DISPLAY_GAMMA) * 255.0 * 255.0 / 280.0).round() as u8)
+                .collect::<Vec<u8>>()
+        })
+        .collect()
+}
+
+pub fn save_png(path: &Path, width: usize, height: usize, rgb: &[u8]) -> Result<(), io::Error> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgb)?;
+    Ok(())
+}
+
+/// reads a PNG as RGB888, whatever its color type
+pub fn load_png(path: &Path) -> Result<(usize, usize, Vec<u8>), io::Error> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut buffer)?;
+
+    let rgb = match reader.output_color_type().0 {
+        png::ColorType::RGB => buffer,
+        png::ColorType::RGBA => buffer.chunks(4).flat_map(|p| p[..3].to_vec()).collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|g| vec![*g; 3]).collect(),
+        png::ColorType::GrayscaleAlpha => buffer.chunks(2).flat_map(|p| vec![p[0]; 3]).collect(),
+        t => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported png color type => {:?}", t))),
+    };
+    Ok((info.width as usize, info.height as usize, rgb))
+}
+
+/// counts mismatching pixels, also returning an image with them painted red
+/// over a faded copy of the actual one
+pub fn diff(expected: &[u8], actual: &[u8]) -> (usize, Vec<u8>) {
+    let mut mismatches = 0;
+    let mut image = Vec::with_capacity(actual.len());
+    for (e, a) in expected.chunks(3).zip(actual.chunks(3)) {
+        if e == a {
+            image.extend(a.iter().map(|c| 0xC0 + c / 4));
+        } else {
+            mismatches += 1;
+            image.extend_from_slice(&[0xFF, 0x00, 0x00]);
+        }
+    }
+    (mismatches, image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn temp_png(name: &str) -> std::path::PathBuf {
+        env::temp_dir().join(format!("gboy-screen-{}-{}.png", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_load_png_round_trips_and_expands_grey() {
+        let rgb: Vec<u8> = (0..2 * 3 * 3).map(|i| i as u8 * 10).collect();
+        let path = temp_png("rgb");
+        save_png(&path, 3, 2, &rgb).unwrap();
+        let loaded = load_png(&path);
+
+        let grey = temp_png("grey");
+        let mut encoder = png::Encoder::new(File::create(&grey).unwrap(), 2, 1);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header().unwrap().write_image_data(&[0x10, 0xF0]).unwrap();
+        let expanded = load_png(&grey);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&grey);
+
+        assert_eq!(loaded.unwrap(), (3, 2, rgb));
+        assert_eq!(expanded.unwrap(), (2, 1, vec![0x10, 0x10, 0x10, 0xF0, 0xF0, 0xF0]));
+        assert!(load_png(&temp_png("missing")).is_err());
+    }
+
+    #[test]
+    fn test_palette_from_str() {
+        assert_eq!("Green".parse(), Ok(DmgPalette::Green));
+        assert_eq!("gray".parse(), Ok(DmgPalette::Grey));
+        let custom: DmgPalette = "#FFFFFF, aaaaaa,555555,000000".parse().unwrap();
+        assert_eq!(custom, DmgPalette::Custom(DMG_GREYS));
+        // custom palettes print back as they parse
+        assert_eq!(custom.to_string().parse(), Ok(custom));
+
+        assert!("purple".parse::<DmgPalette>().is_err());
+        assert!("FFFFFF,AAAAAA,555555".parse::<DmgPalette>().is_err());
+        assert!("FFFFFF,AAAAAA,555555,00000G".parse::<DmgPalette>().is_err());
+        assert!("FFFFFF,AAAAAA,555555,+0000".parse::<DmgPalette>().is_err());
+    }
+
+    #[test]
+    fn test_color_correction_from_str() {
+        assert_eq!("LCD".parse(), Ok(ColorCorrection::Lcd));
+        assert_eq!("none".parse(), Ok(ColorCorrection::Off));
+        assert!("vivid".parse::<ColorCorrection>().is_err());
+    }
+
+    #[test]
+    fn test_post_process_hotkeys() {
+        let mut post = PostProcess::default();
+        let shades = [0, 3];
+        assert_eq!(post.shades(&shades), vec![0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00]);
+        post.cycle_palette();
+        post.cycle_palette();
+        assert_eq!(post.palette, DmgPalette::Pocket);
+        assert_eq!(post.shades(&shades)[..3], POCKET[0]);
+        post.palette = DmgPalette::Custom(DMG_GREEN);
+        post.cycle_palette();
+        assert_eq!(post.palette, DmgPalette::Grey);
+
+        let colors = [0x7FFF, 0x0000, 0x001F, 0x0010];
+        assert_eq!(post.colors(&colors)[..3], [0xFF, 0xFF, 0xFF]);
+        post.toggle_correction();
+        let corrected = post.colors(&colors);
+        // white is dimmed, black stays black and pure red bleeds into the others
+        assert!(corrected[..3].iter().all(|c| *c > 0xE0 && *c < 0xFF));
+        assert_eq!(corrected[3..6], [0, 0, 0]);
+        assert!(corrected[6] > corrected[8] && corrected[8] > corrected[7] && corrected[7] > 0);
+        // the lcd gamma darkens mid tones more than plain scaling does
+        assert!(corrected[9] < rgb555_to_rgb(&colors[3..])[0]);
+    }
+
+    #[test]
+    fn test_diff_paints_mismatches_red() {
+        let expected = [0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF];
+        let actual = [0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFE];
+        let (mismatches, image) = diff(&expected, &actual);
+
+        assert_eq!(mismatches, 1);
+        // matching pixels are faded towards white
+        assert_eq!(image, vec![0xC0, 0xC0, 0xC0, 0xFF, 0x00, 0x00]);
+        assert_eq!(diff(&expected, &expected).0, 0);
+    }
+}
@@ -0,0 +1,238 @@
+use std::io;
+
+use super::model::Model;
+
+const MAGIC: &[u8; 8] = b"GBOYSTAT";
+/// bumped whenever the layout of any component changes, states from other
+/// versions are refused
+pub const FORMAT_VERSION: u16 = 1;
+pub const EMULATOR_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// what a state was taken from, checked before anything is restored
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateHeader {
+    pub format_version: u16,
+    pub emulator_version: String,
+    pub model: Model,
+    pub rom_sha1: String,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn model_id(model: Model) -> u8 {
+    match model {
+        Model::Dmg => 0,
+        Model::CgbCompat => 1,
+        Model::Cgb => 2,
+        Model::Sgb => 3,
+    }
+}
+
+fn model_from_id(id: u8) -> Result<Model, io::Error> {
+    match id {
+        0 => Ok(Model::Dmg),
+        1 => Ok(Model::CgbCompat),
+        2 => Ok(Model::Cgb),
+        3 => Ok(Model::Sgb),
+        _ => Err(invalid(format!("unknown model id {} in state", id))),
+    }
+}
+
+/// little endian, length prefixed byte writer components dump themselves with
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.data.extend_from_slice(value);
+    }
+
+    pub fn u16s(&mut self, value: &[u16]) {
+        self.u32(value.len() as u32);
+        for v in value {
+            self.u16(*v);
+        }
+    }
+
+    pub fn string(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data, at: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], io::Error> {
+        if self.data.len() - self.at < len {
+            return Err(invalid("state is truncated".to_string()));
+        }
+        let slice = &self.data[self.at..self.at + len];
+        self.at += len;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, io::Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, io::Error> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, io::Error> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, io::Error> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, io::Error> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    pub fn usize(&mut self) -> Result<usize, io::Error> {
+        Ok(self.u64()? as usize)
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], io::Error> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    /// bytes that must fill exactly the given buffer, like fixed size rams
+    pub fn bytes_into(&mut self, buffer: &mut [u8]) -> Result<(), io::Error> {
+        let bytes = self.bytes()?;
+        if bytes.len() != buffer.len() {
+            return Err(invalid(format!("state holds {} bytes where {} are expected", bytes.len(), buffer.len())));
+        }
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    pub fn u16s(&mut self) -> Result<Vec<u16>, io::Error> {
+        let len = self.u32()? as usize;
+        (0..len).map(|_| self.u16()).collect()
+    }
+
+    pub fn u16s_into(&mut self, buffer: &mut [u16]) -> Result<(), io::Error> {
+        let values = self.u16s()?;
+        if values.len() != buffer.len() {
+            return Err(invalid(format!("state holds {} words where {} are expected", values.len(), buffer.len())));
+        }
+        buffer.copy_from_slice(&values);
+        Ok(())
+    }
+
+    pub fn string(&mut self) -> Result<String, io::Error> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|e| invalid(e.to_string()))
+    }
+
+    pub fn finished(&self) -> bool {
+        self.at == self.data.len()
+    }
+}
+
+impl StateHeader {
+    pub fn new(model: Model, rom_sha1: String) -> StateHeader {
+        StateHeader {
+            format_version: FORMAT_VERSION,
+            emulator_version: EMULATOR_VERSION.to_string(),
+            model,
+            rom_sha1,
+        }
+    }
+
+    pub fn write(&self, w: &mut StateWriter) {
+        for b in MAGIC {
+            w.u8(*b);
+        }
+        w.u16(self.format_version);
+        w.string(&self.emulator_version);
+        w.u8(model_id(self.model));
+        w.string(&self.rom_sha1);
+    }
+
+    pub fn read(r: &mut StateReader) -> Result<StateHeader, io::Error> {
+        if r.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(invalid("not a save state".to_string()));
+        }
+        let format_version = r.u16()?;
+        if format_version != FORMAT_VERSION {
+            return Err(invalid(format!(
+                "save state format version {} is not supported, this build reads version {}",
+                format_version, FORMAT_VERSION
+            )));
+        }
+        Ok(StateHeader {
+            format_version,
+            emulator_version: r.string()?,
+            model: model_from_id(r.u8()?)?,
+            rom_sha1: r.string()?,
+        })
+    }
+
+    /// refuses states, and movies, made from another rom or model
+    pub fn check(&self, expected: &StateHeader) -> Result<(), io::Error> {
+        if self.rom_sha1 != expected.rom_sha1 {
+            return Err(invalid(format!(
+                "made for another rom, sha1 {} while running {}",
+                self.rom_sha1, expected.rom_sha1
+            )));
+        }
+        if self.model != expected.model {
+            return Err(invalid(format!(
+                "made on {}, emulating {}",
+                self.model, expected.model
+            )));
+        }
+        if self.emulator_version != expected.emulator_version {
+            warn!("made with gboy {}, running {}", self.emulator_version, expected.emulator_version);
+        }
+        Ok(())
+    }
+}
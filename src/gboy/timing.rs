@@ -0,0 +1,268 @@
+use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// frames a second of the real hardware, 4194304 Hz over 70224 cycles a frame
+pub const FRAME_RATE: f64 = 4_194_304.0 / 70_224.0;
+
+// further behind than this the pacer gives up catching up, after a stall
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// multipliers over real time, 0 runs uncapped
+#[derive(Debug, Clone)]
+pub struct TimingConfig {
+    pub speed: f64,
+    pub fast_forward: f64,
+    pub slow_motion: f64,
+}
+
+impl Default for TimingConfig {
+    fn default() -> TimingConfig {
+        TimingConfig {
+            speed: 1.0,
+            fast_forward: 4.0,
+            slow_motion: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Speed {
+    Normal,
+    FastForward,
+    SlowMotion,
+}
+
+/// actions frontends bind to keys, the headless runner reads them by name
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Hotkey {
+    /// toggles between fast forward and normal speed
+    FastForward,
+    /// toggles between slow motion and normal speed
+    SlowMotion,
+    Normal,
+    /// toggles pause
+    Pause,
+    /// runs one more frame, pausing first if needed
+    FrameAdvance,
+    /// toggles stepping back a rewind snapshot every frame instead of running
+    Rewind,
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Hotkey, String> {
+        match s {
+            "fast-forward" | "ff" => Ok(Hotkey::FastForward),
+            "slow-motion" | "slow" => Ok(Hotkey::SlowMotion),
+            "normal" => Ok(Hotkey::Normal),
+            "pause" => Ok(Hotkey::Pause),
+            "advance" | "" => Ok(Hotkey::FrameAdvance),
+            "rewind" => Ok(Hotkey::Rewind),
+            _ => Err(format!("unknown hotkey {:?}, expected fast-forward, slow-motion, normal, pause, advance or rewind", s)),
+        }
+    }
+}
+
+struct State {
+    config: TimingConfig,
+    speed: Speed,
+    paused: bool,
+    // frames left to run while paused
+    advance: usize,
+    rewinding: bool,
+}
+
+/// pacing controls shared between the emulation and a frontend, clones
+/// drive the same machine so hotkeys can be handled on another thread
+#[derive(Clone)]
+pub struct TimingControl {
+    shared: Arc<(Mutex<State>, Condvar)>,
+}
+
+impl TimingControl {
+    pub fn new(config: TimingConfig) -> TimingControl {
+        let state = State {
+            config,
+            speed: Speed::Normal,
+            paused: false,
+            advance: 0,
+            rewinding: false,
+        };
+        TimingControl { shared: Arc::new((Mutex::new(state), Condvar::new())) }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.shared.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn changed(&self) {
+        self.shared.1.notify_all();
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.state().speed
+    }
+
+    pub fn set_speed(&self, speed: Speed) {
+        info!("running at {:?} speed", speed);
+        self.state().speed = speed;
+        self.changed();
+    }
+
+    /// times real time the emulation runs at, None when uncapped
+    pub fn multiplier(&self) -> Option<f64> {
+        let state = self.state();
+        let multiplier = match state.speed {
+            Speed::Normal => state.config.speed,
+            Speed::FastForward => state.config.fast_forward,
+            Speed::SlowMotion => state.config.slow_motion,
+        };
+        if multiplier > 0.0 { Some(multiplier) } else { None }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.state().paused
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        info!("{}", if paused { "paused" } else { "resumed" });
+        let mut state = self.state();
+        state.paused = paused;
+        state.advance = 0;
+        drop(state);
+        self.changed();
+    }
+
+    /// runs the given frames and pauses again
+    pub fn frame_advance(&self, frames: usize) {
+        let mut state = self.state();
+        state.paused = true;
+        state.advance += frames;
+        drop(state);
+        self.changed();
+    }
+
+    /// whether the rewind key is held, frames then step back rather than run
+    pub fn rewinding(&self) -> bool {
+        self.state().rewinding
+    }
+
+    pub fn set_rewinding(&self, rewinding: bool) {
+        info!("{}", if rewinding { "rewinding" } else { "rewind released" });
+        self.state().rewinding = rewinding;
+        self.changed();
+    }
+
+    pub fn hotkey(&self, hotkey: Hotkey) {
+        let toggle = |speed| if self.speed() == speed { Speed::Normal } else { speed };
+        match hotkey {
+            Hotkey::FastForward => self.set_speed(toggle(Speed::FastForward)),
+            Hotkey::SlowMotion => self.set_speed(toggle(Speed::SlowMotion)),
+            Hotkey::Normal => self.set_speed(Speed::Normal),
+            Hotkey::Pause => self.set_paused(!self.paused()),
+            Hotkey::FrameAdvance => self.frame_advance(1),
+            Hotkey::Rewind => self.set_rewinding(!self.rewinding()),
+        }
+    }
+
+    /// waits until the frame about to start may run, false when it had to
+    /// wait so pacing starts over
+    fn wait_frame(&self) -> bool {
+        let mut state = self.state();
+        let mut waited = false;
+        while state.paused && state.advance == 0 {
+            waited = true;
+            state = self.shared.1.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        if state.paused {
+            state.advance -= 1;
+        }
+        !waited
+    }
+}
+
+/// keeps the emulation on the wall clock, sleeping on every frame boundary
+/// until the frame is due
+pub struct Pacer {
+    control: TimingControl,
+    // start of the paced stretch and multiplier it runs at
+    origin: Option<(Instant, f64)>,
+    frames: u32,
+}
+
+impl Pacer {
+    pub fn new(control: TimingControl) -> Pacer {
+        Pacer {
+            control,
+            origin: None,
+            frames: 0,
+        }
+    }
+
+    pub fn control(&self) -> &TimingControl {
+        &self.control
+    }
+
+    /// frame boundary, blocks while paused and then until the frame is due
+    pub fn frame(&mut self) {
+        if !self.control.wait_frame() {
+            self.origin = None;
+        }
+        let multiplier = match self.control.multiplier() {
+            Some(m) => m,
+            None => {
+                self.origin = None;
+                return;
+            },
+        };
+        let origin = match self.origin {
+            Some((origin, m)) if m == multiplier => origin,
+            _ => {
+                self.origin = Some((Instant::now(), multiplier));
+                self.frames = 0;
+                return;
+            },
+        };
+
+        self.frames += 1;
+        let due = origin + Duration::from_secs_f64(self.frames as f64 / (FRAME_RATE * multiplier));
+        let now = Instant::now();
+        if due > now {
+            thread::sleep(due - now);
+        } else if now - due > MAX_LAG {
+            trace!("pacer {:?} behind, starting over", now - due);
+            self.origin = Some((now, multiplier));
+            self.frames = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_frame_advance_runs_given_frames() {
+        let control = TimingControl::new(TimingConfig { speed: 0.0, ..TimingConfig::default() });
+        control.frame_advance(2);
+        let (sender, receiver) = mpsc::channel();
+        let mut pacer = Pacer::new(control.clone());
+        let runner = thread::spawn(move || {
+            for frame in 0..3 {
+                pacer.frame();
+                sender.send(frame).unwrap();
+            }
+        });
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(1)), Ok(0));
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(1)), Ok(1));
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        control.hotkey(Hotkey::Pause);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(1)), Ok(2));
+        runner.join().unwrap();
+    }
+}
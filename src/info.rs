@@ -0,0 +1,140 @@
+use std::path::{Path, PathBuf};
+use serde_json::{json, Value};
+use structopt::StructOpt;
+
+use crate::gboy::cartridge::{self, Header, Validation};
+use crate::gboy::rom;
+
+#[derive(StructOpt, Debug)]
+#[structopt(rename_all = "kebab-case")]
+pub struct InfoOpt {
+    /// print a json array, one object per rom
+    #[structopt(long)]
+    json: bool,
+
+    /// rom to pick on zip archives holding several
+    #[structopt(long)]
+    zip_entry: Option<String>,
+
+    /// roms to inspect
+    #[structopt(parse(from_os_str), required = true)]
+    roms: Vec<PathBuf>,
+}
+
+/// header, checksums and hashes of a rom
+struct Info {
+    path: String,
+    header: Header,
+    validation: Validation,
+    header_checksum_calculated: u8,
+    global_checksum_calculated: u16,
+    rom_length: usize,
+    crc32: u32,
+    sha1: String,
+}
+
+fn inspect(path: &Path, zip_entry: Option<&str>) -> Result<Info, String> {
+    let rom = rom::load(path, zip_entry).map_err(|e| e.to_string())?;
+    let mut cartridge = cartridge::inspect(rom).map_err(|e| e.to_string())?;
+    Ok(Info {
+        path: path.to_string_lossy().to_string(),
+        validation: cartridge.validate(),
+        header_checksum_calculated: cartridge.calculate_header_checksum(),
+        global_checksum_calculated: cartridge.calculate_global_checksum(),
+        rom_length: cartridge.rom_len(),
+        crc32: cartridge.crc32(),
+        sha1: cartridge.sha1(),
+        header: cartridge.header().clone(),
+    })
+}
+
+fn valid(valid: bool) -> &'static str {
+    if valid { "ok" } else { "MISMATCH" }
+}
+
+impl Info {
+    fn json(&self) -> Value {
+        let h = &self.header;
+        json!({
+            "path": self.path,
+            "title": h.title,
+            "manufacturer": h.manufacturer,
+            "cgb": format!("{:?}", h.cgb),
+            "sgb": h.sgb,
+            "licensee": h.licensee(),
+            "old_licensee": format!("0x{:02X}", h.old_licensee),
+            "new_licensee": h.new_licensee,
+            "cartridge_type": format!("0x{:02X}", h.cartridge_type),
+            "cartridge_type_name": h.cartridge_type_name(),
+            "mapper": h.mapper_name(),
+            "battery": h.has_battery(),
+            "rom_size": h.rom_size,
+            "rom_length": self.rom_length,
+            "ram_size": h.ram_size,
+            "destination": h.destination_name(),
+            "version": h.version,
+            "header_checksum": format!("0x{:02X}", h.header_checksum),
+            "header_checksum_calculated": format!("0x{:02X}", self.header_checksum_calculated),
+            "global_checksum": format!("0x{:04X}", h.global_checksum),
+            "global_checksum_calculated": format!("0x{:04X}", self.global_checksum_calculated),
+            "logo_valid": self.validation.logo,
+            "header_checksum_valid": self.validation.header_checksum,
+            "global_checksum_valid": self.validation.global_checksum,
+            "rom_size_valid": self.validation.rom_size,
+            "crc32": format!("{:08x}", self.crc32),
+            "sha1": self.sha1,
+        })
+    }
+
+    fn print_text(&self) {
+        let h = &self.header;
+        println!("======     {}     =======", self.path);
+        println!("Title: {}", h.title);
+        println!("Manufacturer Code: {}", h.manufacturer.as_deref().unwrap_or("-"));
+        println!("CGB Flag: {:?}", h.cgb);
+        println!("SGB Flag: {}", h.sgb);
+        println!("Licensee: {} (old 0x{:02X}, new {})", h.licensee(), h.old_licensee, h.new_licensee);
+        println!("Type: {} (0x{:02X})", h.cartridge_type_name(), h.cartridge_type);
+        println!("Mapper: {}", h.mapper_name().unwrap_or("unsupported"));
+        println!("Battery: {}", h.has_battery());
+        println!("ROM Size: {} bytes declared, {} bytes read ({})", h.rom_size, self.rom_length, valid(self.validation.rom_size));
+        println!("RAM Size: {} bytes", h.ram_size);
+        println!("Destination Code: {}", h.destination_name());
+        println!("Version: {}", h.version);
+        println!("Nintendo Logo: {}", valid(self.validation.logo));
+        println!(
+            "Header Checksum: 0x{:02X} calculated: 0x{:02X} ({})",
+            h.header_checksum, self.header_checksum_calculated, valid(self.validation.header_checksum)
+        );
+        println!(
+            "Global Checksum: 0x{:04X} calculated: 0x{:04X} ({})",
+            h.global_checksum, self.global_checksum_calculated, valid(self.validation.global_checksum)
+        );
+        println!("CRC32: {:08x}", self.crc32);
+        println!("SHA-1: {}", self.sha1);
+    }
+}
+
+/// prints every rom header, returns false if any of them couldn't be read
+pub fn info(opt: InfoOpt) -> bool {
+    let mut ok = true;
+    let mut all = Vec::new();
+    for path in &opt.roms {
+        match inspect(path, opt.zip_entry.as_deref()) {
+            Ok(i) if opt.json => all.push(i.json()),
+            Ok(i) => i.print_text(),
+            Err(e) => {
+                ok = false;
+                if opt.json {
+                    all.push(json!({ "path": path.to_string_lossy(), "error": e }));
+                } else {
+                    eprintln!("{:?} => {}", path, e);
+                }
+            },
+        }
+    }
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&Value::Array(all)).unwrap());
+    }
+    ok
+}
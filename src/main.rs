@@ -1,8 +1,9 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io;
+use std::sync::{Arc, Mutex};
 use std::{thread};
 
 #[macro_use]
@@ -12,7 +13,9 @@ use env_logger::Env;
 use log::{Record, LevelFilter};
 use env_logger::Builder;
 
+mod config;
 mod gboy;
+mod info;
 
 const NONE_LOG_LEVEL: usize = 0;
 
@@ -22,20 +25,294 @@ struct Opt {
     #[structopt(long, short, parse(from_occurrences))]
     debug: usize,
 
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// emulates a rom
+    #[structopt(name = "run")]
+    Run(Box<RunOpt>),
+
+    /// prints cartridge header, checksums and hashes of roms
+    #[structopt(name = "info")]
+    Info(info::InfoOpt),
+}
+
+/// structopt 0.2 only derives plain subcommand types, the boxed run options
+/// forward to the derived ones so Command stays small
+trait BoxedSubcommand {
+    fn augment_clap<'a, 'b>(app: structopt::clap::App<'a, 'b>) -> structopt::clap::App<'a, 'b>;
+    fn is_subcommand() -> bool;
+}
+
+impl BoxedSubcommand for Box<RunOpt> {
+    fn augment_clap<'a, 'b>(app: structopt::clap::App<'a, 'b>) -> structopt::clap::App<'a, 'b> {
+        RunOpt::augment_clap(app)
+    }
+
+    fn is_subcommand() -> bool {
+        RunOpt::is_subcommand()
+    }
+}
+
+impl StructOpt for Box<RunOpt> {
+    fn clap<'a, 'b>() -> structopt::clap::App<'a, 'b> {
+        RunOpt::clap()
+    }
+
+    fn from_clap(matches: &structopt::clap::ArgMatches) -> Self {
+        Box::new(RunOpt::from_clap(matches))
+    }
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(rename_all = "kebab-case")]
+struct RunOpt {
     /// boot room, it's skipped if none is passed in
     #[structopt(long, short, parse(from_os_str))]
     bootrom: Option<PathBuf>,
 
+    /// directory holding dmg_boot.bin, cgb_boot.bin and sgb_boot.bin, the one
+    /// matching the emulated model is used
+    #[structopt(long, parse(from_os_str), conflicts_with = "bootrom")]
+    bootrom_dir: Option<PathBuf>,
+
+    /// hardware to emulate: dmg, cgb or sgb, picked from the cartridge header otherwise
+    #[structopt(long)]
+    model: Option<gboy::model::Model>,
+
     /// enable tui debugger
     #[structopt(long)]
     debugger: bool,
-    
+
+    /// wait for a link cable peer on "host:port" or unix socket path
+    #[structopt(long)]
+    link_listen: Option<String>,
+
+    /// plug link cable into a peer listening on "host:port" or unix socket path
+    #[structopt(long, conflicts_with = "link-listen")]
+    link_connect: Option<String>,
+
+    /// run a second, headless console on ROM in this process, plugged into the link port
+    #[structopt(long, parse(from_os_str), raw(conflicts_with_all = r#"&["link-listen", "link-connect"]"#))]
+    link_peer: Option<PathBuf>,
+
+    /// run without any window nor debugger, printing the exit reason
+    #[structopt(long, conflicts_with = "debugger")]
+    headless: bool,
+
+    /// quit after N frames
+    #[structopt(long)]
+    frames: Option<usize>,
+
+    /// quit after N clock cycles
+    #[structopt(long)]
+    cycles: Option<usize>,
+
+    /// quit once PC reaches ADDR, hexadecimal with optional 0x prefix
+    #[structopt(long, parse(try_from_str = "parse_address"))]
+    until_pc: Option<usize>,
+
+    /// quit once the serial output contains the given text, may be repeated
+    #[structopt(long)]
+    until_serial: Vec<String>,
+
+    /// quit on the LD B,B software breakpoint
+    #[structopt(long)]
+    until_breakpoint: bool,
+
+    /// save a PNG screenshot on exit
+    #[structopt(long, parse(from_os_str))]
+    screenshot: Option<PathBuf>,
+
+    /// compare the exit screen against a reference PNG, exit code is 3 on mismatch
+    #[structopt(long, parse(from_os_str))]
+    compare_screenshot: Option<PathBuf>,
+
+    /// where to write the diff image when the comparison fails
+    #[structopt(long, parse(from_os_str), requires = "compare-screenshot")]
+    diff_image: Option<PathBuf>,
+
+    /// print registers on exit
+    #[structopt(long)]
+    dump_registers: bool,
+
+    /// write the 64KiB address space on exit
+    #[structopt(long, parse(from_os_str))]
+    dump_memory: Option<PathBuf>,
+
+    /// refuse to boot roms with a bad logo, checksum or size
+    #[structopt(long)]
+    strict: bool,
+
+    /// ips, ups or bps patch applied to the rom, defaults to one next to the rom with the same stem
+    #[structopt(long, parse(from_os_str))]
+    patch: Option<PathBuf>,
+
+    /// rom to pick when the rom is a zip archive holding several
+    #[structopt(long)]
+    zip_entry: Option<String>,
+
+    /// directory for battery saves, defaults to the rom directory
+    #[structopt(long, parse(from_os_str))]
+    save_dir: Option<PathBuf>,
+
+    /// restore the machine from a save state file before running
+    #[structopt(long, parse(from_os_str))]
+    load_state: Option<PathBuf>,
+
+    /// restore the machine from numbered save state slot, <rom>.ss<N> in the save directory
+    #[structopt(long, conflicts_with = "load-state")]
+    load_slot: Option<u8>,
+
+    /// write a save state file on exit
+    #[structopt(long, parse(from_os_str))]
+    save_state: Option<PathBuf>,
+
+    /// write numbered save state slot on exit
+    #[structopt(long, conflicts_with = "save-state")]
+    save_slot: Option<u8>,
+
+    /// keep snapshots to step back to, with the rewind hotkey or --rewind-steps
+    #[structopt(long)]
+    rewind: bool,
+
+    /// frames between rewind snapshots
+    #[structopt(long, default_value = "1")]
+    rewind_interval: usize,
+
+    /// seconds of play the rewind buffer holds
+    #[structopt(long, default_value = "10")]
+    rewind_seconds: usize,
+
+    /// memory budget for the rewind buffer, in MiB
+    #[structopt(long, default_value = "64")]
+    rewind_memory: usize,
+
+    /// step back N snapshots once the run stops, before screenshots and dumps
+    #[structopt(long, requires = "rewind")]
+    rewind_steps: Option<usize>,
+
+    /// log joypad input per frame to a movie file, written on exit
+    #[structopt(long, parse(from_os_str))]
+    record_movie: Option<PathBuf>,
+
+    /// replay a movie file, starting from its own state or power on. BizHawk
+    /// .bk2 and VisualBoyAdvance .vbm movies are imported from power on
+    #[structopt(long, parse(from_os_str), raw(conflicts_with_all = r#"&["record-movie", "load-state", "load-slot"]"#))]
+    play_movie: Option<PathBuf>,
+
+    /// press buttons from a frame on, frame:buttons as in 120:a+start, an
+    /// empty list releases them. May be repeated, recorded movies log them
+    #[structopt(long, raw(number_of_values = "1"))]
+    input: Vec<gboy::cpu::ScriptedInput>,
+
+    /// frames between machine hashes stored on recorded movies, 0 stores none
+    #[structopt(long, default_value = "60")]
+    movie_hash_interval: usize,
+
+    /// stop playback on the first hash mismatch, exit code is 4
+    #[structopt(long, requires = "play-movie")]
+    verify_movie: bool,
+
+    /// times real time to run at, 0 runs uncapped. Defaults to 1, or uncapped when headless
+    #[structopt(long)]
+    speed: Option<f64>,
+
+    /// times real time the fast forward hotkey runs at, 0 runs uncapped
+    #[structopt(long, default_value = "4")]
+    fast_forward: f64,
+
+    /// times real time the slow motion hotkey runs at
+    #[structopt(long, default_value = "0.5")]
+    slow_motion: f64,
+
+    /// start paused, frames are then stepped with the advance hotkey
+    #[structopt(long)]
+    paused: bool,
+
+    /// read hotkeys from stdin, one per line: fast-forward, slow-motion, normal,
+    /// pause, advance [N], rewind, palette or color-correction, an empty line
+    /// advances a frame. End of input resumes and lets go of rewind
+    #[structopt(long)]
+    stdin_controls: bool,
+
+    /// DMG colors: grey, green, pocket or four comma separated hex colors, lightest first
+    #[structopt(long)]
+    palette: Option<gboy::screen::DmgPalette>,
+
+    /// CGB color output: off or lcd, which mimics the washed out CGB screen
+    #[structopt(long)]
+    color_correction: Option<gboy::screen::ColorCorrection>,
+
+    /// json settings file, defaults to $XDG_CONFIG_HOME/gboy/config.json
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
     /// rom to emulate 
     #[structopt(parse(from_os_str))]
     gamerom: PathBuf,
 
 }
 
+/// second console plugged into the first one, stopping on the same frame and
+/// cycle limits. Its battery is left alone so both can run the same rom
+fn link_peer(rom: PathBuf, cable: gboy::link::LinkCable, model: Option<gboy::model::Model>, frames: Option<usize>, cycles: Option<usize>) {
+    thread::spawn(move || {
+        let game_rom = match gboy::rom::load(&rom, None) {
+            Ok(g) => g,
+            Err(e) => return error!("Could not load link peer rom {:?} => {}", rom, e),
+        };
+        let mut peer = match gboy::cpu::initialize(game_rom, None) {
+            Ok(c) => c,
+            Err(e) => return error!("Could not start link peer => {}", e),
+        };
+        let model = gboy::model::select(peer.cartridge_header(), model);
+        peer.set_model(model);
+        peer.set_limits(gboy::cpu::RunLimits { frames, cycles, ..Default::default() });
+        peer.connect_serial(Box::new(cable));
+        peer.bootup(None);
+        let reason = peer.run();
+        info!("link peer stopped => {:?}", reason);
+    });
+}
+
+/// hotkeys for the headless runner, so scripts can step frames and switch
+/// the colors the screenshot is taken with
+fn stdin_controls(control: gboy::timing::TimingControl, post: Arc<Mutex<gboy::screen::PostProcess>>) {
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => break,
+            };
+            let mut words = line.split_whitespace();
+            let hotkey = words.next().unwrap_or("");
+            match hotkey {
+                "palette" => post.lock().unwrap_or_else(|e| e.into_inner()).cycle_palette(),
+                "color-correction" => post.lock().unwrap_or_else(|e| e.into_inner()).toggle_correction(),
+                _ => match (hotkey.parse::<gboy::timing::Hotkey>(), words.next().map(|n| n.parse::<usize>())) {
+                    (Ok(gboy::timing::Hotkey::FrameAdvance), Some(Ok(frames))) => control.frame_advance(frames),
+                    (Ok(h), None) => control.hotkey(h),
+                    (Ok(_), Some(_)) => error!("Could not read hotkey {:?}", line),
+                    (Err(e), _) => error!("{}", e),
+                },
+            }
+        }
+        // nobody left to step frames
+        control.set_rewinding(false);
+        control.set_paused(false);
+    });
+}
+
+fn parse_address(src: &str) -> Result<usize, std::num::ParseIntError> {
+    let src = src.trim_start_matches("0x").trim_start_matches("0X");
+    usize::from_str_radix(src, 16)
+}
+
 fn log_level(lvl: usize) -> String {
     let levels = ["none", "error", "warn", "info", "debug", "trace"];
     if lvl >= levels.len(){
@@ -45,6 +322,36 @@ fn log_level(lvl: usize) -> String {
     }
 }
 
+/// <rom>.sav, next to the rom unless a save directory is given
+fn battery_save_path(gamerom: &Path, save_dir: Option<&PathBuf>) -> PathBuf {
+    let save = gboy::rom::sibling(gamerom, "sav");
+    match (save_dir, save.file_name()) {
+        (Some(dir), Some(name)) => dir.join(name),
+        _ => save,
+    }
+}
+
+/// screen post processing from the command line, then the config file
+fn post_process(opt: &RunOpt) -> gboy::screen::PostProcess {
+    let path = opt.config.clone().or_else(config::default_path);
+    let config = match path {
+        Some(ref p) if opt.config.is_some() || p.exists() => match config::load(p) {
+            Ok(c) => c,
+            Err(e) => panic!("Could not load config {:?} => {}", p, e),
+        },
+        _ => config::Config::default(),
+    };
+    gboy::screen::PostProcess {
+        palette: opt.palette.clone().or(config.palette).unwrap_or_default(),
+        correction: opt.color_correction.or(config.color_correction).unwrap_or_default(),
+    }
+}
+
+/// <rom>.ss<N>, placed like battery saves
+fn state_slot_path(gamerom: &Path, save_dir: Option<&PathBuf>, slot: u8) -> PathBuf {
+    battery_save_path(gamerom, save_dir).with_extension(format!("ss{}", slot))
+}
+
 fn load_file(path: PathBuf) -> Result<Vec<u8>, io::Error> {
    let mut file = File::open(path)?;
    let mut program_buffer = Vec::<u8>::new();
@@ -52,9 +359,60 @@ fn load_file(path: PathBuf) -> Result<Vec<u8>, io::Error> {
    Ok(program_buffer)
 }
 
+fn compare_screenshot(reference: &Path, diff_image: Option<&Path>, screen: &(usize, usize, Vec<u8>)) -> bool {
+    let (screen_width, screen_height, rgb) = screen;
+    let (width, height, expected) = match gboy::screen::load_png(reference) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("screenshot: could not load reference {:?} => {}", reference, e);
+            return false;
+        },
+    };
+    if width != *screen_width || height != *screen_height {
+        println!("screenshot: reference is {}x{}, screen is {}x{}", width, height, screen_width, screen_height);
+        return false;
+    }
+
+    let (mismatches, image) = gboy::screen::diff(&expected, rgb);
+    if mismatches == 0 {
+        println!("screenshot: matches");
+        return true;
+    }
+    println!("screenshot: {} pixels differ", mismatches);
+    if let Some(path) = diff_image {
+        if let Err(e) = gboy::screen::save_png(path, width, height, &image) {
+            error!("Could not save diff image => {}", e);
+        }
+    }
+    false
+}
+
 fn main() {
     let opt = Opt::from_args();
-    let mut debugger : Option<Box<dyn gboy::cpu::CpuDebugger>> = if opt.debugger {
+    let with_debugger = match &opt.command {
+        Command::Run(r) => r.debugger,
+        _ => false,
+    };
+
+    if with_debugger {
+        //env_logger::from_env(Env::default().default_filter_or(log_level(NONE_LOG_LEVEL))).init();
+    } else {
+        env_logger::from_env(Env::default().default_filter_or(log_level(opt.debug))).init();
+    }
+    
+    debug!("{:?}", opt); 
+    match opt.command {
+        Command::Run(r) => run(*r),
+        Command::Info(i) => {
+            if !info::info(i) {
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+fn run(opt: RunOpt) {
+    let debugger : Option<Box<dyn gboy::cpu::CpuDebugger>> = if opt.debugger {
         match gboy::debugger::initialize() {
             Ok(d) => Some(Box::new(d)),
             Err(e) => {
@@ -65,32 +423,197 @@ fn main() {
     } else {
         None
     };
-    
-    if debugger.is_some() {
-        //env_logger::from_env(Env::default().default_filter_or(log_level(NONE_LOG_LEVEL))).init();
+
+    let post = Arc::new(Mutex::new(post_process(&opt)));
+    let save_path = battery_save_path(&opt.gamerom, opt.save_dir.as_ref());
+    let game_rom = match gboy::rom::load(&opt.gamerom, opt.zip_entry.as_deref()) {
+        Ok(g) => g,
+        Err(e) => panic!("{}", e),
+    };
+    let patch = match &opt.patch {
+        Some(p) => Some(p.clone()),
+        None => gboy::patch::find(&opt.gamerom),
+    };
+    let game_rom = match patch {
+        Some(patch) => {
+            info!("applying patch {:?}", patch);
+            match gboy::patch::load(&patch, game_rom) {
+                Ok(g) => g,
+                Err(e) => panic!("Could not apply patch {:?} => {}", patch, e),
+            }
+        },
+        None => game_rom,
+    };
+
+    let link = if let Some(address) = &opt.link_listen {
+        Some(gboy::link::listen(address))
+    } else if let Some(address) = &opt.link_connect {
+        Some(gboy::link::connect(address))
     } else {
-        env_logger::from_env(Env::default().default_filter_or(log_level(opt.debug))).init();
+        opt.link_peer.as_ref().map(|rom| gboy::link::pair().map(|(local, peer)| {
+            link_peer(rom.clone(), peer, opt.model, opt.frames, opt.cycles);
+            local
+        }))
+    };
+
+    let mut console = match gboy::cpu::initialize(game_rom, debugger) {
+        Ok(c) => c,
+        Err(e) => panic!("{}", e),
+    };
+    let validation = console.validate_cartridge();
+    for w in validation.warnings() {
+        warn!("Cartridge validation => {}", w);
     }
-    
-    debug!("{:?}", opt); 
-    let boot_rom = if opt.bootrom.is_some() {
-        match load_file(opt.bootrom.unwrap()) {
+    if opt.strict && !validation.is_valid() {
+        panic!("Refusing to boot in strict mode => {}", validation.warnings().join(", "));
+    }
+    match link {
+        Some(Ok(l)) => console.connect_serial(Box::new(l)),
+        Some(Err(e)) => panic!("Could not plug link cable => {}", e),
+        None => {},
+    }
+    let limits = gboy::cpu::RunLimits {
+        frames: opt.frames,
+        cycles: opt.cycles,
+        until_pc: opt.until_pc,
+        until_serial: opt.until_serial.clone(),
+        until_breakpoint: opt.until_breakpoint,
+    };
+    let waiting_condition = limits.until_pc.is_some() || !limits.until_serial.is_empty() || limits.until_breakpoint;
+    console.set_limits(limits);
+    if let Err(e) = console.attach_battery_save(&save_path) {
+        error!("Could not load battery save {:?} => {}", save_path, e);
+    }
+    let model = gboy::model::select(console.cartridge_header(), opt.model);
+    console.set_model(model);
+
+    let bootrom = match (&opt.bootrom, &opt.bootrom_dir) {
+        (Some(path), _) => Some(path.clone()),
+        (None, Some(dir)) => Some(dir.join(model.bootrom_name())),
+        _ => None,
+    };
+    let boot_rom = match bootrom {
+        Some(path) => match load_file(path) {
             Ok(b) => Some(b),
             Err(e) => panic!("{}", e),
-        }
-    } else { 
-        None 
+        },
+        None => None,
     };
+    trace!("boot_rom => {:?}", boot_rom);
+    console.bootup(boot_rom);
 
-    let game_rom = match load_file(opt.gamerom){
-        Ok(g) => g,
-        Err(e) => panic!("{}", e),
+    let load_state = match (&opt.load_state, opt.load_slot) {
+        (Some(path), _) => Some(path.clone()),
+        (None, Some(slot)) => Some(state_slot_path(&opt.gamerom, opt.save_dir.as_ref(), slot)),
+        _ => None,
+    };
+    if let Some(path) = load_state {
+        if let Err(e) = console.load_state_file(&path) {
+            panic!("Could not load state {:?} => {}", path, e);
+        }
+    }
+    if let Some(path) = &opt.play_movie {
+        let running = console.state_header();
+        let played = gboy::movie::Movie::open(path, &running, console.cartridge_header())
+            .and_then(|m| console.play_movie(m, opt.verify_movie));
+        if let Err(e) = played {
+            panic!("Could not play movie {:?} => {}", path, e);
+        }
+    }
+    if opt.record_movie.is_some() {
+        console.record_movie(opt.load_state.is_some() || opt.load_slot.is_some(), opt.movie_hash_interval);
+    }
+    if !opt.input.is_empty() {
+        console.script_input(opt.input.clone());
+    }
+    if opt.rewind {
+        console.enable_rewind(gboy::rewind::RewindConfig {
+            interval: opt.rewind_interval,
+            seconds: opt.rewind_seconds,
+            memory: opt.rewind_memory * 1024 * 1024,
+        });
+    }
+    let speed = match opt.speed {
+        Some(s) => Some(s),
+        None if !opt.headless => Some(1.0),
+        None if opt.paused || opt.stdin_controls => Some(0.0),
+        None => None,
     };
+    if let Some(speed) = speed {
+        let control = gboy::timing::TimingControl::new(gboy::timing::TimingConfig {
+            speed,
+            fast_forward: opt.fast_forward,
+            slow_motion: opt.slow_motion,
+        });
+        if opt.paused {
+            control.set_paused(true);
+        }
+        if opt.stdin_controls {
+            stdin_controls(control.clone(), post.clone());
+        }
+        console.enable_timing(control);
+    }
+    let reason = console.run();
+    if let Some(steps) = opt.rewind_steps {
+        let stepped = (0..steps).take_while(|_| console.rewind_step()).count();
+        info!("rewound {} of {} snapshots", stepped, steps);
+    }
 
-    trace!("boot_rom => {:?}", boot_rom);
+    if let (Some(path), Some(movie)) = (&opt.record_movie, console.movie()) {
+        match movie.save(path) {
+            Ok(_) => info!("movie of {} frames saved to {:?}", movie.inputs.len(), path),
+            Err(e) => error!("Could not save movie {:?} => {}", path, e),
+        }
+    }
 
-    let mut console = gboy::cpu::initialize(game_rom, debugger);
-    console.bootup(boot_rom);
-    console.run();
+    let save_state = match (&opt.save_state, opt.save_slot) {
+        (Some(path), _) => Some(path.clone()),
+        (None, Some(slot)) => Some(state_slot_path(&opt.gamerom, opt.save_dir.as_ref(), slot)),
+        _ => None,
+    };
+    if let Some(path) = save_state {
+        if let Err(e) = console.save_state_file(&path) {
+            error!("Could not save state {:?} => {}", path, e);
+        }
+    }
 
+    let screen = console.screen(&post.lock().unwrap_or_else(|e| e.into_inner()));
+    if let Some(path) = &opt.screenshot {
+        if let Err(e) = gboy::screen::save_png(path, screen.0, screen.1, &screen.2) {
+            error!("Could not save screenshot => {}", e);
+        }
+    }
+    let screenshot_matches = match &opt.compare_screenshot {
+        Some(path) => compare_screenshot(path, opt.diff_image.as_deref(), &screen),
+        None => true,
+    };
+    if let Some(path) = &opt.dump_memory {
+        if let Err(e) = console.dump_memory(path) {
+            error!("Could not dump memory => {}", e);
+        }
+    }
+    if opt.dump_registers {
+        console.dump_registers();
+    }
+
+    if opt.headless {
+        let serial = String::from_utf8_lossy(console.serial_output());
+        if !serial.is_empty() {
+            println!("======     SERIAL     =======");
+            println!("{}", serial);
+        }
+        if let Some(frame) = console.movie_desync() {
+            println!("movie desync at frame {}", frame);
+        }
+        println!("exit reason: {:?}", reason);
+        // 0 when the run stopped where it was asked to, scripts rely on it
+        let code = match reason {
+            gboy::cpu::ExitReason::Quit => 1,
+            gboy::cpu::ExitReason::Desync => 4,
+            _ if !screenshot_matches => 3,
+            gboy::cpu::ExitReason::Frames | gboy::cpu::ExitReason::Cycles if waiting_condition => 2,
+            _ => 0,
+        };
+        std::process::exit(code);
+    }
 }
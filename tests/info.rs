@@ -0,0 +1,60 @@
+//! gboy info on the harness rom, as text and as json
+//!
+//! cargo test --test info
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use serde_json::Value;
+
+const GBOY: &str = env!("CARGO_BIN_EXE_gboy");
+
+fn rom() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("roms")
+        .join("tests")
+        .join("gboy")
+        .join("harness_pass.gb")
+}
+
+fn info(args: &[&str], roms: &[&Path]) -> Output {
+    Command::new(GBOY).arg("info").args(args).args(roms).output().unwrap()
+}
+
+#[test]
+fn test_info_json() {
+    let missing = rom().with_file_name("missing.gb");
+    let output = info(&["--json"], &[&rom(), &missing]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let all: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let all = all.as_array().unwrap();
+    assert_eq!(all.len(), 2);
+    let harness = &all[0];
+    assert_eq!(harness["title"], "GBOY HARNESS");
+    assert_eq!(harness["cgb"], "Dmg");
+    assert_eq!(harness["mapper"], "None");
+    assert_eq!(harness["cartridge_type"], "0x00");
+    assert_eq!(harness["rom_size"], 0x8000);
+    assert_eq!(harness["rom_length"], 0x8000);
+    assert_eq!(harness["manufacturer"], Value::Null);
+    for check in &["logo_valid", "header_checksum_valid", "global_checksum_valid", "rom_size_valid"] {
+        assert_eq!(harness[*check], true, "{}", check);
+    }
+    assert_eq!(harness["header_checksum"], harness["header_checksum_calculated"]);
+    assert_eq!(harness["sha1"].as_str().map(str::len), Some(40));
+
+    assert_eq!(all[1]["path"], missing.to_string_lossy().as_ref());
+    assert!(all[1]["error"].is_string());
+}
+
+#[test]
+fn test_info_text() {
+    let output = info(&[], &[&rom()]);
+    assert_eq!(output.status.code(), Some(0));
+    let text = String::from_utf8_lossy(&output.stdout);
+    assert!(text.contains("Title: GBOY HARNESS"));
+    assert!(text.contains("Mapper: None"));
+    assert!(text.contains("Manufacturer Code: -"));
+    assert!(text.contains("Nintendo Logo: ok"));
+}
@@ -0,0 +1,107 @@
+//! Movies: playing back a recording must end on the very same machine, and
+//! foreign movies must either play or be refused naming what they need
+//!
+//! cargo test --test movies
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const GBOY: &str = env!("CARGO_BIN_EXE_gboy");
+const FRAMES: usize = 30;
+
+fn gboy(dir: &Path, args: &[&str]) -> Output {
+    let rom = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("roms")
+        .join("tests")
+        .join("mealybug-tearoom-tests")
+        .join("m3_scx_low_3_bits.gb");
+    Command::new(GBOY)
+        .arg("run")
+        .arg("--headless")
+        .arg("--model").arg("dmg")
+        .arg("--save-dir").arg(dir)
+        .args(args)
+        .arg(rom)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_movie_playback_matches_recording() {
+    let dir = env::temp_dir().join(format!("gboy-movies-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+    let frames = FRAMES.to_string();
+    let input = ["--input", "5:start", "--input", "10:a+right", "--input", "20:"];
+
+    gboy(&dir, &[&["--frames", &frames, "--movie-hash-interval", "5", "--record-movie", &path("run.movie"), "--dump-memory", &path("recorded.bin")], &input[..]].concat());
+    let played = gboy(&dir, &["--frames", &frames, "--play-movie", &path("run.movie"), "--verify-movie", "--dump-memory", &path("played.bin")]);
+    let ended = gboy(&dir, &["--play-movie", &path("run.movie"), "--verify-movie"]);
+    gboy(&dir, &["--frames", &frames, "--dump-memory", &path("idle.bin")]);
+    let movie = fs::read(path("run.movie")).unwrap();
+    let (recorded, played_back, idle) = (fs::read(path("recorded.bin")).unwrap(), fs::read(path("played.bin")).unwrap(), fs::read(path("idle.bin")).unwrap());
+    let _ = fs::remove_dir_all(&dir);
+
+    // one byte a frame, start then a and right, released on frame 20
+    let inputs = [vec![0x00], vec![0x80; 5], vec![0x11; 10], vec![0x00]].concat();
+    assert!(movie.windows(inputs.len()).any(|w| w == &inputs[..]), "recorded inputs missing from the movie");
+    assert!(recorded == played_back, "played back memory differs from the recorded run");
+    assert!(recorded != idle, "input made no difference to the run");
+    assert!(String::from_utf8_lossy(&played.stdout).contains("exit reason: Frames"));
+    assert!(String::from_utf8_lossy(&ended.stdout).contains("exit reason: MovieEnd"));
+    assert_eq!(ended.status.code(), Some(0));
+}
+
+/// power on VBM for one controller, input words at 0x100
+fn vbm(inputs: &[u16]) -> Vec<u8> {
+    let mut data = vec![0; 0x100];
+    data[..4].copy_from_slice(b"VBM\x1A");
+    data[0x0C..0x10].copy_from_slice(&(inputs.len() as u32).to_le_bytes());
+    data[0x15] = 0x01;
+    data[0x16] = 0x04;
+    data[0x3C..0x40].copy_from_slice(&0x100u32.to_le_bytes());
+    for input in inputs {
+        data.extend_from_slice(&input.to_le_bytes());
+    }
+    data
+}
+
+fn bk2(lines: &[&str]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default();
+    zip.start_file("Header.txt", options).unwrap();
+    zip.write_all(b"MovieVersion BizHawk v2.0\nPlatform GB\nCore Gambatte\n").unwrap();
+    zip.start_file("Input Log.txt", options).unwrap();
+    writeln!(zip, "[Input]\nLogKey:#Up|Down|Left|Right|Start|Select|B|A|Power|#").unwrap();
+    for line in lines {
+        writeln!(zip, "{}", line).unwrap();
+    }
+    writeln!(zip, "[/Input]").unwrap();
+    zip.finish().unwrap().into_inner()
+}
+
+#[test]
+fn test_foreign_movies_import() {
+    let dir = env::temp_dir().join(format!("gboy-movie-imports-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+
+    fs::write(path("clean.vbm"), vbm(&[0x0000, 0x0008, 0x0011, 0x0000])).unwrap();
+    fs::write(path("reset.vbm"), vbm(&[0x0000, 0x0000, 0x0800, 0x0000])).unwrap();
+    fs::write(path("clean.bk2"), bk2(&["|........P|", "|....S....|", "|...R...A.|"])).unwrap();
+    fs::write(path("power.bk2"), bk2(&["|.........|", "|........P|"])).unwrap();
+    let played: Vec<Output> = ["clean.vbm", "reset.vbm", "clean.bk2", "power.bk2"]
+        .iter()
+        .map(|movie| gboy(&dir, &["--play-movie", &path(movie)]))
+        .collect();
+    let _ = fs::remove_dir_all(&dir);
+
+    for output in played.iter().step_by(2) {
+        assert!(String::from_utf8_lossy(&output.stdout).contains("exit reason: MovieEnd"));
+    }
+    assert!(String::from_utf8_lossy(&played[1].stderr).contains("resets on frame 2"));
+    assert!(String::from_utf8_lossy(&played[3].stderr).contains("power on frame 1"));
+}
@@ -0,0 +1,255 @@
+//! Runs every rom under roms/tests headlessly and prints a result table
+//!
+//! cargo test --test roms -- --nocapture
+//!
+//! GBOY_ROM_CYCLES overrides the clock cycles budget given to each rom.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const GBOY: &str = env!("CARGO_BIN_EXE_gboy");
+const DEFAULT_CYCLES: usize = 100_000_000;
+
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL: [u8; 6] = [0x42; 6];
+
+// blargg's newer roms report through cartridge ram, 0xA000 status then signature
+const BLARGG_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const BLARGG_RUNNING: u8 = 0x80;
+
+/// roms known to pass, any of them not passing anymore is a regression
+const KNOWN_PASSING: &[&str] = &["gboy/harness_pass.gb"];
+
+/// suites judged by screenshot comparison against upstream references, which
+/// are not vendored, run them by hand with --compare-screenshot
+const SCREENSHOT_SUITES: &[&str] = &["mealybug-tearoom-tests"];
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Passed,
+    Failed(String),
+    Unknown(String),
+    Skipped(String),
+}
+
+struct Run {
+    exit_reason: String,
+    serial: String,
+    registers: Vec<(String, u8)>,
+    memory: Vec<u8>,
+}
+
+fn test_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .expect("roms/tests not readable")
+        .map(|e| e.unwrap().path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            // sources only carry the roms assembly
+            if path.file_name().is_some_and(|n| n == "source") {
+                continue;
+            }
+            test_roms(&path, roms);
+        } else if path.extension().is_some_and(|e| e == "gb" || e == "gbc") {
+            roms.push(path);
+        }
+    }
+}
+
+fn cycles_budget() -> usize {
+    env::var("GBOY_ROM_CYCLES")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(DEFAULT_CYCLES)
+}
+
+fn emulate(rom: &Path, index: usize) -> Result<Run, String> {
+    let dump = env::temp_dir().join(format!("gboy-roms-{}-{}.bin", std::process::id(), index));
+    // battery saves go away with the run, so every run starts from blank cartridge ram
+    let saves = env::temp_dir().join(format!("gboy-roms-{}-{}-saves", std::process::id(), index));
+    let output = Command::new(GBOY)
+        .arg("run")
+        .arg("--headless")
+        .arg("--model").arg("dmg")
+        .arg("--cycles").arg(cycles_budget().to_string())
+        .arg("--until-serial").arg("Passed")
+        .arg("--until-serial").arg("Failed")
+        .arg("--until-breakpoint")
+        .arg("--dump-registers")
+        .arg("--dump-memory").arg(&dump)
+        .arg("--save-dir").arg(&saves)
+        .arg(rom)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .map_err(|e| format!("could not spawn gboy => {}", e))?;
+    let memory = fs::read(&dump).unwrap_or_default();
+    let _ = fs::remove_file(&dump);
+    let _ = fs::remove_dir_all(&saves);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut run = Run {
+        exit_reason: String::from("none"),
+        serial: String::new(),
+        registers: Vec::new(),
+        memory,
+    };
+    let mut in_serial = false;
+    for line in stdout.lines() {
+        if line.starts_with("======") {
+            in_serial = line.contains("SERIAL");
+        } else if let Some(reason) = line.strip_prefix("exit reason: ") {
+            run.exit_reason = reason.trim().to_string();
+        } else if in_serial {
+            run.serial.push_str(line);
+            run.serial.push('\n');
+        } else if let Some((name, value)) = line.split_once(" == ") {
+            if let Ok(v) = u8::from_str_radix(value.trim().trim_start_matches("0x"), 16) {
+                run.registers.push((name.trim().to_string(), v));
+            }
+        }
+    }
+    if !output.stderr.is_empty() && run.exit_reason == "none" {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // panic message follows the "thread 'main' panicked at" line
+        let panic = stderr.lines().skip_while(|l| !l.contains("panicked at")).nth(1).unwrap_or("");
+        return Err(format!("crashed: {}", panic));
+    }
+    Ok(run)
+}
+
+fn judge(run: &Run) -> Outcome {
+    if run.serial.contains("Passed") {
+        return Outcome::Passed;
+    }
+    if run.serial.contains("Failed") {
+        let last = run.serial.lines().rfind(|l| !l.trim().is_empty()).unwrap_or("");
+        return Outcome::Failed(format!("serial: {}", last.trim()));
+    }
+
+    if run.memory.len() > 0xA004 && run.memory[0xA001..0xA004] == BLARGG_SIGNATURE {
+        let status = run.memory[0xA000];
+        let text: String = run.memory[0xA004..]
+            .iter()
+            .take_while(|b| **b != 0)
+            .map(|b| *b as char)
+            .collect();
+        return match status {
+            0x0 => Outcome::Passed,
+            BLARGG_RUNNING => Outcome::Unknown(format!("still running, exit {}", run.exit_reason)),
+            s => Outcome::Failed(format!("status {:#x}: {}", s, text.trim())),
+        };
+    }
+
+    let signature: Vec<u8> = ["B", "C", "D", "E", "H", "L"]
+        .iter()
+        .filter_map(|n| run.registers.iter().find(|(r, _)| r == n).map(|(_, v)| *v))
+        .collect();
+    if run.exit_reason == "Breakpoint" && signature == MOONEYE_PASS {
+        return Outcome::Passed;
+    }
+    if run.exit_reason == "Breakpoint" && signature == MOONEYE_FAIL {
+        return Outcome::Failed(String::from("mooneye fail signature"));
+    }
+
+    Outcome::Unknown(format!("no result, exit {}", run.exit_reason))
+}
+
+#[test]
+fn test_roms_table() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("roms").join("tests");
+    let mut roms = Vec::new();
+    test_roms(&root, &mut roms);
+
+    let mut regressions = Vec::new();
+    let mut passed = 0;
+    println!("{:<60} RESULT", "ROM");
+    for (i, rom) in roms.iter().enumerate() {
+        let name = rom.strip_prefix(&root).unwrap().to_string_lossy().to_string();
+        let outcome = if SCREENSHOT_SUITES.iter().any(|s| name.starts_with(s)) {
+            Outcome::Skipped(String::from("judged by reference screenshot"))
+        } else {
+            match emulate(rom, i) {
+                Ok(run) => judge(&run),
+                Err(e) => Outcome::Unknown(e),
+            }
+        };
+
+        let result = match &outcome {
+            Outcome::Passed => String::from("PASS"),
+            Outcome::Failed(why) => format!("FAIL ({})", why),
+            Outcome::Unknown(why) => format!("???? ({})", why),
+            Outcome::Skipped(why) => format!("SKIP ({})", why),
+        };
+        println!("{:<60} {}", name, result);
+
+        if outcome == Outcome::Passed {
+            passed += 1;
+        } else if KNOWN_PASSING.contains(&name.as_str()) {
+            regressions.push(name);
+        }
+    }
+    println!("{} of {} roms passed", passed, roms.len());
+
+    assert!(regressions.is_empty(), "roms not passing anymore => {:?}", regressions);
+}
+
+fn synthetic(exit_reason: &str, serial: &str, registers: &[(&str, u8)], memory: Vec<u8>) -> Run {
+    Run {
+        exit_reason: exit_reason.to_string(),
+        serial: serial.to_string(),
+        registers: registers.iter().map(|(n, v)| (n.to_string(), *v)).collect(),
+        memory,
+    }
+}
+
+fn blargg_memory(status: u8, text: &str) -> Vec<u8> {
+    let mut memory = vec![0; 0x10000];
+    memory[0xA000] = status;
+    memory[0xA001..0xA004].copy_from_slice(&BLARGG_SIGNATURE);
+    memory[0xA004..0xA004 + text.len()].copy_from_slice(text.as_bytes());
+    memory
+}
+
+fn mooneye(signature: [u8; 6]) -> Vec<(&'static str, u8)> {
+    ["B", "C", "D", "E", "H", "L"].iter().cloned().zip(signature.iter().cloned()).collect()
+}
+
+#[test]
+fn test_judge_serial() {
+    assert_eq!(judge(&synthetic("Serial", "cpu_instrs\n\nPassed\n", &[], Vec::new())), Outcome::Passed);
+    assert_eq!(
+        judge(&synthetic("Serial", "01:ok\n02:01\n\nFailed 1 tests\n", &[], Vec::new())),
+        Outcome::Failed(String::from("serial: Failed 1 tests"))
+    );
+}
+
+#[test]
+fn test_judge_blargg_memory() {
+    assert_eq!(judge(&synthetic("Cycles", "", &[], blargg_memory(0x0, "Passed"))), Outcome::Passed);
+    assert_eq!(
+        judge(&synthetic("Cycles", "", &[], blargg_memory(0x2, "wave ram\n"))),
+        Outcome::Failed(String::from("status 0x2: wave ram"))
+    );
+    assert_eq!(
+        judge(&synthetic("Cycles", "", &[], blargg_memory(BLARGG_RUNNING, ""))),
+        Outcome::Unknown(String::from("still running, exit Cycles"))
+    );
+}
+
+#[test]
+fn test_judge_mooneye_registers() {
+    assert_eq!(judge(&synthetic("Breakpoint", "", &mooneye(MOONEYE_PASS), Vec::new())), Outcome::Passed);
+    assert_eq!(
+        judge(&synthetic("Breakpoint", "", &mooneye(MOONEYE_FAIL), Vec::new())),
+        Outcome::Failed(String::from("mooneye fail signature"))
+    );
+    // the signature only counts once the breakpoint is hit
+    assert_eq!(
+        judge(&synthetic("Cycles", "", &mooneye(MOONEYE_PASS), Vec::new())),
+        Outcome::Unknown(String::from("no result, exit Cycles"))
+    );
+}
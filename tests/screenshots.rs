@@ -0,0 +1,65 @@
+//! Screenshot comparison: a screen must match a screenshot of itself, and a
+//! reference off by one pixel must fail with exit code 3 and a diff image
+//!
+//! cargo test --test screenshots
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const GBOY: &str = env!("CARGO_BIN_EXE_gboy");
+
+fn rom() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("roms")
+        .join("tests")
+        .join("gboy")
+        .join("harness_pass.gb")
+}
+
+fn gboy(dir: &Path, args: &[&Path], flags: &[&str]) -> Output {
+    let mut command = Command::new(GBOY);
+    command
+        .arg("run")
+        .arg("--headless")
+        .arg("--model").arg("dmg")
+        .arg("--palette").arg("grey")
+        .arg("--cycles").arg("1000000")
+        .arg("--until-breakpoint")
+        .arg("--save-dir").arg(dir);
+    for (flag, arg) in flags.iter().zip(args) {
+        command.arg(flag).arg(arg);
+    }
+    command.arg(rom()).output().unwrap()
+}
+
+#[test]
+fn test_screenshot_comparison() {
+    let dir = env::temp_dir().join(format!("gboy-screenshots-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (screen, reference, diff) = (dir.join("screen.png"), dir.join("reference.png"), dir.join("diff.png"));
+
+    gboy(&dir, &[&screen], &["--screenshot"]);
+    let matching = gboy(&dir, &[&screen], &["--compare-screenshot"]);
+
+    // same screen with the first pixel flipped
+    let decoder = png::Decoder::new(fs::File::open(&screen).unwrap());
+    let (info, mut reader) = decoder.read_info().unwrap();
+    let mut rgb = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut rgb).unwrap();
+    rgb[0] ^= 0xFF;
+    let mut encoder = png::Encoder::new(fs::File::create(&reference).unwrap(), info.width, info.height);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().unwrap().write_image_data(&rgb).unwrap();
+    let differing = gboy(&dir, &[&reference, &diff], &["--compare-screenshot", "--diff-image"]);
+    let diff_written = diff.exists();
+    let _ = fs::remove_dir_all(&dir);
+
+    assert_eq!(matching.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&matching.stdout).contains("screenshot: matches"));
+    assert_eq!(differing.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&differing.stdout).contains("screenshot: 1 pixels differ"));
+    assert!(diff_written);
+}
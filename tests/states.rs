@@ -0,0 +1,82 @@
+//! Save states: a run resumed from a state must end exactly like an
+//! uninterrupted one, and states must be refused on another rom or model
+//!
+//! cargo test --test states
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const GBOY: &str = env!("CARGO_BIN_EXE_gboy");
+const HALF_CYCLES: usize = 1_000_000;
+const ROM: &str = "m3_scx_low_3_bits.gb";
+
+fn rom(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("roms")
+        .join("tests")
+        .join("mealybug-tearoom-tests")
+        .join(name)
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("gboy-states-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn gboy(dir: &Path, rom_name: &str, model: &str, cycles: usize, args: &[&str]) -> Output {
+    Command::new(GBOY)
+        .arg("run")
+        .arg("--headless")
+        .arg("--model").arg(model)
+        .arg("--cycles").arg(cycles.to_string())
+        .arg("--save-dir").arg(dir)
+        .args(args)
+        .arg(rom(rom_name))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_state_resumes_run() {
+    let dir = temp_dir("resume");
+    let (state, resumed, straight) = (dir.join("half.state"), dir.join("resumed.bin"), dir.join("straight.bin"));
+    let (state, resumed, straight) = (&*state.to_string_lossy(), &*resumed.to_string_lossy(), &*straight.to_string_lossy());
+
+    gboy(&dir, ROM, "dmg", HALF_CYCLES, &["--save-state", state]);
+    gboy(&dir, ROM, "dmg", 2 * HALF_CYCLES, &["--load-state", state, "--dump-memory", resumed]);
+    gboy(&dir, ROM, "dmg", 2 * HALF_CYCLES, &["--dump-memory", straight]);
+    let same = fs::read(resumed).unwrap() == fs::read(straight).unwrap();
+
+    let other_model = gboy(&dir, ROM, "cgb", 2 * HALF_CYCLES, &["--load-state", state]);
+    let other_rom = gboy(&dir, "m3_bgp_change.gb", "dmg", 2 * HALF_CYCLES, &["--load-state", state]);
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(same, "resumed run memory differs from the uninterrupted one");
+    assert!(!other_model.status.success());
+    assert!(String::from_utf8_lossy(&other_model.stderr).contains("emulating CGB"));
+    assert!(!other_rom.status.success());
+    assert!(String::from_utf8_lossy(&other_rom.stderr).contains("made for another rom"));
+}
+
+#[test]
+fn test_slots_live_next_to_the_battery_save() {
+    let dir = temp_dir("slots");
+    let (resumed, straight) = (dir.join("resumed.bin"), dir.join("straight.bin"));
+    let (resumed, straight) = (&*resumed.to_string_lossy(), &*straight.to_string_lossy());
+
+    gboy(&dir, ROM, "dmg", HALF_CYCLES, &["--save-slot", "3"]);
+    let slot = dir.join("m3_scx_low_3_bits.ss3");
+    let saved = slot.exists();
+    gboy(&dir, ROM, "dmg", 2 * HALF_CYCLES, &["--load-slot", "3", "--dump-memory", resumed]);
+    gboy(&dir, ROM, "dmg", 2 * HALF_CYCLES, &["--dump-memory", straight]);
+    let same = fs::read(resumed).unwrap() == fs::read(straight).unwrap();
+    let empty = gboy(&dir, ROM, "dmg", HALF_CYCLES, &["--load-slot", "4"]);
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(saved, "slot 3 was not saved to {:?}", slot);
+    assert!(same, "run resumed from a slot differs from the uninterrupted one");
+    assert!(!empty.status.success());
+}
@@ -0,0 +1,111 @@
+//! Timing: paced runs must keep to the wall clock, and a paused run must
+//! only move on the frames it is stepped
+//!
+//! cargo test --test timing
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+const GBOY: &str = env!("CARGO_BIN_EXE_gboy");
+// a little under 60 frames a second
+const FRAME: f64 = 1.0 / 60.0;
+
+fn rom() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("roms")
+        .join("tests")
+        .join("mealybug-tearoom-tests")
+        .join("m3_scx_low_3_bits.gb")
+}
+
+fn spawn(args: &[&str]) -> Child {
+    Command::new(GBOY)
+        .arg("run")
+        .arg("--headless")
+        .arg("--model").arg("dmg")
+        .args(args)
+        .arg(rom())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap()
+}
+
+fn gboy(args: &[&str], stdin: &str) -> Output {
+    let mut child = spawn(args);
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_slow_motion_keeps_to_wall_clock() {
+    let start = Instant::now();
+    let output = gboy(&["--frames", "20", "--speed", "0.5"], "");
+    let elapsed = start.elapsed();
+
+    assert!(String::from_utf8_lossy(&output.stdout).contains("exit reason: Frames"));
+    // the first frame boundary only starts the clock, one more of slack
+    assert!(elapsed >= Duration::from_secs_f64(18.0 * FRAME / 0.5), "20 frames at half speed took {:?}", elapsed);
+}
+
+#[test]
+fn test_paused_run_steps_frames_from_stdin() {
+    let dir = std::env::temp_dir().join(format!("gboy-timing-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (stepped, straight) = (dir.join("stepped.bin"), dir.join("straight.bin"));
+
+    // stdin is kept open, end of input would resume the run by itself
+    let mut child = spawn(&["--frames", "5", "--paused", "--stdin-controls", "--dump-memory", &stepped.to_string_lossy()]);
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"advance 3\n\n").unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    let waiting = child.try_wait().unwrap().is_none();
+    stdin.write_all(b"advance\n").unwrap();
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() && start.elapsed() < Duration::from_secs(10) {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let finished = child.try_wait().unwrap().is_some();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+
+    gboy(&["--frames", "5", "--dump-memory", &straight.to_string_lossy()], "");
+    let same = std::fs::read(&stepped).unwrap() == std::fs::read(&straight).unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(waiting, "run finished before its fifth frame was stepped");
+    assert!(finished, "run didn't finish once its fifth frame was stepped");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("exit reason: Frames"));
+    assert!(same, "stepped run memory differs from the straight one");
+}
+
+#[test]
+fn test_rewind_hotkey_steps_back_during_run() {
+    let dir = std::env::temp_dir().join(format!("gboy-rewind-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (rewound, straight) = (dir.join("rewound.bin"), dir.join("straight.bin"));
+
+    let mut child = spawn(&["--frames", "12", "--paused", "--stdin-controls", "--rewind", "--dump-memory", &rewound.to_string_lossy()]);
+    let mut stdin = child.stdin.take().unwrap();
+    // ten frames forward, three back, then four forward again ends on frame 11.
+    // Hotkeys apply as soon as they are read, each batch of frames is let run first
+    for command in ["advance 10", "rewind", "advance 3", "rewind", "advance 4"] {
+        writeln!(stdin, "{}", command).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    let waiting = child.try_wait().unwrap().is_none();
+    stdin.write_all(b"advance\n").unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+
+    gboy(&["--frames", "12", "--dump-memory", &straight.to_string_lossy()], "");
+    let same = std::fs::read(&rewound).unwrap() == std::fs::read(&straight).unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(waiting, "run finished as if no frame was stepped back");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("exit reason: Frames"));
+    assert!(same, "replaying rewound frames ended on another machine");
+}